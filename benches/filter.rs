@@ -0,0 +1,62 @@
+// Times `AppState::filter` given the filter it last applied, against a full rebuild.
+// The crate having no library target, its modules are compiled into the benchmark,
+// most of their items and the imports of their test modules going unused here
+#![allow(dead_code, unused_imports)]
+
+#[path = "../src/app.rs"]
+mod app;
+#[path = "../src/component.rs"]
+mod component;
+#[path = "../src/config.rs"]
+mod config;
+#[path = "../src/header.rs"]
+mod header;
+#[path = "../src/layer.rs"]
+mod layer;
+#[path = "../src/model.rs"]
+mod model;
+#[path = "../src/tui.rs"]
+mod tui;
+#[path = "../src/ui.rs"]
+mod ui;
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use app::AppState;
+use header::Header;
+
+const HEADERS: usize = 100_000;
+
+fn filtered_state() -> AppState {
+    let mut state = AppState {
+        items: (0..HEADERS)
+            .map(|i| {
+                let name = if i % 2 == 0 { "tcp_login" } else { "udp_ping" };
+                Header::from(i, Some(i % 10), format!("{name}_{i}"), String::new())
+            })
+            .collect(),
+        ..AppState::default()
+    };
+    state.filter("tcp login").unwrap();
+    state
+}
+
+fn filter(c: &mut Criterion) {
+    let mut state = filtered_state();
+    c.bench_function("filter unchanged", |b| {
+        b.iter(|| state.filter(black_box("tcp login")).unwrap())
+    });
+
+    let mut state = filtered_state();
+    c.bench_function("filter rebuilt", |b| {
+        b.iter(|| {
+            state.invalidate_filter();
+            state.filter(black_box("tcp login")).unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, filter);
+criterion_main!(benches);
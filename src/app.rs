@@ -1,75 +1,800 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Error, Result};
+use chrono::NaiveDateTime;
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use ratatui::{
-    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    crossterm::event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
+    layout::{Constraint, Position, Rect},
+    style::{Color, Style},
     widgets::{ScrollbarState, TableState},
 };
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthStr;
 
-use crate::component::{EditingInput, InputArena, MainInput};
-use crate::model::{Header, Model};
+use crate::component::{EditingInput, InputArena, InputId, MainInput, ValueType};
+use crate::config::{Config, KeyBindings, Theme};
+use crate::header::Header;
+use crate::layer::LayerFormat;
+use crate::model::{
+    ColumnId, ColumnInfo, DiffLine, LayerEntry, MergeConflict, Model, QueryRow, SortConfig,
+    SortKey, SqlFilter,
+};
+use crate::tui;
+use crate::ui::{breadcrumb_segments, column_header, format_size, LayoutConfig, HIGHLIGHT_SYMBOL};
+
+const ITEM_HEIGHT: usize = 1; // rows of a proto in the table, the scrollbar moving by rows
+const AUTOSAVE_DELAY: Duration = Duration::from_secs(2);
+const SAVED_INDICATOR_DURATION: Duration = Duration::from_millis(1500);
+const SPARKLINE_BUCKETS: std::ops::RangeInclusive<usize> = 8..=60;
+const SPLIT_PERCENT: std::ops::RangeInclusive<u16> = 20..=80;
+const SPLIT_STEP: u16 = 5;
+const ADDED_HIGHLIGHT_DURATION: Duration = Duration::from_secs(1);
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+const ERROR_TOAST_STYLE: Style = Style::new().fg(Color::Black).bg(Color::LightRed);
+const NEW_PROTO_FIELDS: [InputId; 4] = [
+    InputId::NewSession,
+    InputId::NewName,
+    InputId::NewTimestamp,
+    InputId::NewData,
+];
+const EDIT_FIELDS: [InputId; 2] = [InputId::Key, InputId::Value];
+const READ_ONLY_MESSAGE: &str = "Database opened read-only, restart with --read-write to edit it";
+const DEFAULT_VISIBLE_COLUMNS: [ColumnId; 4] = [
+    ColumnId::Name,
+    ColumnId::Session,
+    ColumnId::Timestamp,
+    ColumnId::DataSize,
+];
+
+// transient message floating above the footer until it expires
+#[derive(Clone, Debug)]
+pub struct Toast {
+    pub message: String,
+    pub expires_at: Instant,
+    pub style: Style,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SaveStatus {
+    Clean,
+    Dirty, // an auto-save is pending
+    Saved, // an auto-save just happened
+}
+
+// how the data of the selected proto is shown
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DataViewMode {
+    #[default]
+    Text,
+    Json, // pretty-printed when the data parses as JSON
+    Hex,
+}
+
+impl DataViewMode {
+    pub fn next(self) -> Self {
+        match self {
+            DataViewMode::Text => DataViewMode::Json,
+            DataViewMode::Json => DataViewMode::Hex,
+            DataViewMode::Hex => DataViewMode::Text,
+        }
+    }
+}
+
+// how the filter is matched against the proto names
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FilterMode {
+    #[default]
+    Exact, // whitespace separated substrings, `!` excluding one
+    Regex,
+    Fuzzy, // best scores first
+}
+
+impl FilterMode {
+    pub fn next(self) -> Self {
+        match self {
+            FilterMode::Exact => FilterMode::Fuzzy,
+            FilterMode::Fuzzy => FilterMode::Regex,
+            FilterMode::Regex => FilterMode::Exact,
+        }
+    }
+}
+
+// everything `filtered_indexes` was last computed from, besides the items
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppliedFilter {
+    pub value: String,
+    pub mode: FilterMode,
+    pub fuzzy_threshold: i64,
+    pub bookmarks_only: bool,
+    pub size_filter: Option<(usize, usize)>,
+    pub sort_config: SortConfig,
+}
+
+// aggregates over the filtered protos, shown by the statistics overlay
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub total: usize,
+    pub filtered: usize,
+    pub min_size: Option<usize>,
+    pub max_size: Option<usize>,
+    pub mean_size: Option<f64>,
+    pub earliest: Option<String>,
+    pub latest: Option<String>,
+    pub sessions: usize, // unique session ids, protos without one excluded
+}
+
+// position in the proto list, restored when the same database is opened again
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    pub filter: String,
+    pub selected_index: usize,
+    pub scroll_y: u16, // offset of the proto table
+    pub sort_config: SortConfig,
+    pub wrap_navigation: bool,
+    pub split_percent: u16,          // width of the proto list pane
+    pub filter_history: Vec<String>, // confirmed filters, the most recent last
+}
+
+impl Default for UiState {
+    fn default() -> Self {
+        UiState {
+            filter: String::new(),
+            selected_index: 0,
+            scroll_y: 0,
+            sort_config: SortConfig::default(),
+            wrap_navigation: true,
+            split_percent: 50,
+            filter_history: Vec::new(),
+        }
+    }
+}
+
+// where the UI states and the debug log are kept
+pub fn data_dir() -> Option<PathBuf> {
+    let data_dir = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data_dir.join("semantic-layer-builder"))
+}
+
+impl UiState {
+    // one file per database, named after a CRC32 of its canonical path
+    pub fn path(db_path: &Path) -> Option<PathBuf> {
+        let canonical = db_path.canonicalize().ok()?;
+        let hash = crc32fast::hash(canonical.as_os_str().as_encoded_bytes());
+        Some(data_dir()?.join(format!("{hash:08x}.toml")))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read file `{:?}`", &path))?;
+        toml_edit::de::from_str(&contents)
+            .with_context(|| format!("Invalid UI state `{:?}`", &path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create directory `{:?}`", &dir))?;
+        }
+        std::fs::write(path, toml_edit::ser::to_string(self)?)
+            .with_context(|| format!("Could not write file `{:?}`", &path))
+    }
+}
 
-const ITEM_HEIGHT: usize = 4;
+#[derive(Debug)]
 pub enum CurrentScreen {
     Main(MainInput),
     Editing(EditingInput),
-    Exiting,
+    ConfirmSave {
+        diff: Vec<DiffLine>,
+    },
+    Annotating {
+        rowid: usize,
+    },
+    Diff {
+        left: usize,
+        right: usize,
+    },
+    SchemaView,
+    SqlQuery,
+    ViewPicker,
+    ViewParameters {
+        view: String,
+        fields: Vec<(String, InputId)>, // parameters of the view and their registered inputs
+        focused: usize,
+    },
+    SessionPicker,
+    SizeFilter,
+    Statistics,
+    MergeConflict {
+        key: String,
+        ours: String,
+        theirs: String,
+    },
+    ConfirmDelete {
+        path: Vec<String>, // layer key to remove, from the root
+    },
+    ConfirmDeleteProto {
+        rowid: usize,
+        name: String,
+    },
+    AddProto(InputId), // focused field of the new proto popup
+    Exiting {
+        dirty: bool, // the layer differs from its file
+    },
+}
+
+impl CurrentScreen {
+    // single-line input typed into on this screen
+    pub fn focused_input(&self) -> Option<InputId> {
+        match self {
+            CurrentScreen::Main(MainInput::Filter) => Some(InputId::Filter),
+            CurrentScreen::Main(MainInput::Command) => Some(InputId::Command),
+            CurrentScreen::Editing(focused) => Some(focused.clone().into()),
+            CurrentScreen::Annotating { .. } => Some(InputId::Annotation),
+            CurrentScreen::SizeFilter => Some(InputId::SizeFilter),
+            CurrentScreen::AddProto(focused) => Some(focused.clone()),
+            CurrentScreen::ViewParameters {
+                fields, focused, ..
+            } => fields.get(*focused).map(|(_, id)| id.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CurrentScreen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CurrentScreen::Main(MainInput::None) => write!(f, "main/normal"),
+            CurrentScreen::Main(MainInput::Filter) => write!(f, "main/filter"),
+            CurrentScreen::Main(MainInput::Layer) => write!(f, "main/layer"),
+            CurrentScreen::Main(MainInput::Command) => write!(f, "main/command"),
+            CurrentScreen::Editing(EditingInput::Key) => write!(f, "editing/key"),
+            CurrentScreen::Editing(EditingInput::Value) => write!(f, "editing/value"),
+            CurrentScreen::ConfirmSave { .. } => write!(f, "confirm-save"),
+            CurrentScreen::Annotating { .. } => write!(f, "annotating"),
+            CurrentScreen::Diff { .. } => write!(f, "diff"),
+            CurrentScreen::SchemaView => write!(f, "schema"),
+            CurrentScreen::SqlQuery => write!(f, "sql-query"),
+            CurrentScreen::ViewPicker => write!(f, "view-picker"),
+            CurrentScreen::ViewParameters { .. } => write!(f, "view-parameters"),
+            CurrentScreen::SessionPicker => write!(f, "session-picker"),
+            CurrentScreen::SizeFilter => write!(f, "size-filter"),
+            CurrentScreen::Statistics => write!(f, "statistics"),
+            CurrentScreen::MergeConflict { .. } => write!(f, "merge-conflict"),
+            CurrentScreen::ConfirmDelete { .. } => write!(f, "confirm-delete"),
+            CurrentScreen::ConfirmDeleteProto { .. } => write!(f, "confirm-delete-proto"),
+            CurrentScreen::AddProto(field) => write!(f, "add-proto/{field:?}"),
+            CurrentScreen::Exiting { .. } => write!(f, "exiting"),
+        }
+    }
 }
 
 pub struct AppState {
     pub items: Vec<Header>, // list of all item names found in the SQLite DB
-    pub cached: Option<(usize, String)>, // cached value for the UI
+    pub cached: Option<(Header, Vec<u8>)>, // selected proto and its raw data, TEXT or BLOB
+    pub data_view_mode: DataViewMode, // how the cached value is shown
     pub selected_index: usize, // current state of the TableState, can be derived from state but used to simplified processes
 
     // filtering-specific state
     pub filtered_indexes: Vec<usize>,
+    pub bookmarks: HashSet<usize>, // bookmarked rowids, persisted in the layer
+    pub bookmarks_only: bool,      // only show bookmarked items, whatever the filter
+    pub filter_mode: FilterMode,   // how the filter input is matched
+    pub fuzzy_threshold: i64,      // score to beat in fuzzy mode
+    pub sort_config: SortConfig,   // order of filtered_indexes
+    pub items_sorted_by: Option<SortConfig>, // order SQLite loaded the items in, None for capture order
+    pub size_filter: Option<(usize, usize)>, // inclusive data size range, in bytes
+    pub last_applied_filter: Option<AppliedFilter>, // inputs of filtered_indexes, None to rebuild it
+    pub last_queried_index: Option<usize>, // row `cached` was read for, None once the list changed
+    pub sql_filter: SqlFilter,             // conditions SQLite applies when loading the items
+    pub loading: bool,                     // items being read again in the background
+    pub spinner_frame: u8,                 // 0 to 7, advanced every tick while loading
+
+    // view-specific state
+    pub active_view: Option<String>, // layer view replacing the full proto list
+    pub view_parameters: Vec<(String, String)>, // values bound to the parameters of the active view
+    pub views: Vec<String>,          // names listed by the view picker
+    pub view_selected: usize,        // picker line, 0 being the full proto list
+
+    // session-specific state
+    pub session_counts: HashMap<Option<usize>, usize>, // protos per session, queried by the picker
+    pub session_selected: usize,                       // picker line, 0 being all the sessions
+
+    // multi-select-specific state
+    pub multi_selection: Vec<usize>, // rowids marked with Space, in marking order
+    pub deselect_count: u8,          // Escapes pressed in a row, see `App::undo_selection_step`
+    pub data_diff: Vec<(Option<DiffLine>, Option<DiffLine>)>, // rows of the diff screen
+
+    // layer-specific state
+    pub layer_nav_path: Vec<String>, // current "directory" within the TOML layer
+    pub layer_entries: Vec<LayerEntry>, // entries of the table pointed by layer_nav_path
+    pub layer_state: TableState,     // state of the Table that hold layer entries
+    pub layer_format: LayerFormat,   // format the layer file is read and written in
+    pub layer_json: Option<String>,  // whole layer shown as read-only JSON instead of the entries
+    pub breadcrumb_area: Rect,       // where the layer_nav_path breadcrumb was last drawn
+
+    // editing-specific state
+    pub value_type: ValueType, // type the Value input is parsed as
+    pub layer_key_candidates: Vec<String>, // existing layer keys, for Key autocompletion
+    pub edit_error: Option<String>, // error raised by the last edit validation
+    pub input_overflow_flash: HashMap<InputId, Instant>, // full inputs typed into
+    pub added_highlight: Option<(usize, Instant)>, // rowid of the proto just added
+    pub diff_scroll: u16,      // vertical scroll of the save confirmation diff
+    pub save_status: SaveStatus, // state of the layer file, shown in the footer
+
+    // merge-specific state
+    pub merge_conflicts: Vec<MergeConflict>, // conflicts left to resolve, the first one being shown
+
+    // command-specific state
+    pub command_message: Option<String>, // outcome of the last palette command
+    pub toasts: VecDeque<Toast>,         // transient messages, the first one being shown
+
+    // schema-specific state
+    pub schema: Vec<(String, Vec<ColumnInfo>)>, // columns of every table of the database
+    pub schema_scroll: u16,                     // vertical scroll of the schema view
+
+    // sql-specific state
+    pub sql_result: Option<(Vec<String>, Vec<QueryRow>)>, // columns and rows, shown instead of the protos
+    pub sql_error: Option<String>,                        // error raised by the last custom query
+
+    // statistics-specific state
+    pub sparkline_buckets: usize, // time buckets of the message count sparkline
+    pub cache_stats: (usize, usize), // hits and misses of the data cache of the model
 
     // UI-specific state
-    pub state: TableState,             // state of the Table that hold items
-    pub scroll_state: ScrollbarState,  // state for the scrollbar, synced to the tablestate
-    pub current_screen: CurrentScreen, // to know how which screen the ui is focusing
+    pub visible_columns: Vec<ColumnId>, // columns of the proto table, in order
+    pub column_aliases: HashMap<String, String>, // header names by database column
+    pub theme: Theme,                   // colors used by the ui
+    pub keybindings: KeyBindings,       // keys of the main screen actions
+    pub db_name: String,                // shown in the title
+    pub layer_name: String,             // shown in the title
+    pub page_size: usize,               // number of rows skipped by PageUp / PageDown
+    pub visible_rows: usize,            // proto table rows fitting in the terminal
+    pub wrap_navigation: bool,          // Up / Down go around the ends of the list
+    pub layout: LayoutConfig,           // pane sizes of the main screen
+    pub state: TableState,              // state of the Table that hold items
+    pub scroll_state: ScrollbarState,   // state for the scrollbar, synced to the tablestate
+    pub table_area: Rect,               // where the proto table was last drawn, empty if it was not
+    pub name_width: Option<usize>,      // names were cut to it on the last draw
+    pub hover_tooltip: Option<(u16, u16, String)>, // position and full name of the cut name under the mouse
+    pub current_screen: CurrentScreen,             // to know how which screen the ui is focusing
+}
+
+// empty state, without any item nor filter
+impl Default for AppState {
+    fn default() -> Self {
+        AppState {
+            items: Vec::new(),
+            cached: None,
+            data_view_mode: DataViewMode::default(),
+            selected_index: 0,
+            filtered_indexes: Vec::new(),
+            bookmarks: HashSet::new(),
+            bookmarks_only: false,
+            filter_mode: FilterMode::default(),
+            fuzzy_threshold: Config::default().fuzzy_threshold,
+            sort_config: SortConfig::default(),
+            items_sorted_by: None,
+            size_filter: None,
+            last_applied_filter: None,
+            last_queried_index: None,
+            sql_filter: SqlFilter::default(),
+            loading: false,
+            spinner_frame: 0,
+            active_view: None,
+            view_parameters: Vec::new(),
+            views: Vec::new(),
+            view_selected: 0,
+            session_counts: HashMap::new(),
+            session_selected: 0,
+            multi_selection: Vec::new(),
+            deselect_count: 0,
+            data_diff: Vec::new(),
+            layer_nav_path: Vec::new(),
+            layer_entries: Vec::new(),
+            layer_state: TableState::default().with_selected(0),
+            layer_format: LayerFormat::default(),
+            layer_json: None,
+            breadcrumb_area: Rect::default(),
+            value_type: ValueType::default(),
+            layer_key_candidates: Vec::new(),
+            edit_error: None,
+            input_overflow_flash: HashMap::new(),
+            added_highlight: None,
+            diff_scroll: 0,
+            save_status: SaveStatus::Clean,
+            merge_conflicts: Vec::new(),
+            command_message: None,
+            toasts: VecDeque::new(),
+            schema: Vec::new(),
+            schema_scroll: 0,
+            sql_result: None,
+            sql_error: None,
+            sparkline_buckets: 30,
+            cache_stats: (0, 0),
+            visible_columns: DEFAULT_VISIBLE_COLUMNS.to_vec(),
+            column_aliases: HashMap::new(),
+            theme: Theme::default(),
+            keybindings: KeyBindings::default(),
+            db_name: String::new(),
+            layer_name: String::new(),
+            page_size: Config::default().page_size,
+            visible_rows: 0,
+            wrap_navigation: true,
+            layout: LayoutConfig::default(),
+            state: TableState::default(),
+            scroll_state: ScrollbarState::default(),
+            table_area: Rect::default(),
+            name_width: None,
+            hover_tooltip: None,
+            current_screen: CurrentScreen::Main(MainInput::None),
+        }
+    }
 }
 
 impl AppState {
-    pub fn new(model: &Model) -> Result<Self> {
+    pub fn new(model: &mut Model) -> Result<Self> {
         let protos = model.query_protos()?;
-        let scrollbar_state = ScrollbarState::new((protos.len() - 1) * ITEM_HEIGHT);
+        let scrollbar_state = ScrollbarState::new(protos.len().saturating_sub(1) * ITEM_HEIGHT);
+        // nothing to select in an empty capture
+        let selected = (!protos.is_empty()).then_some(0);
         Ok(AppState {
             items: protos,
-            cached: None,
-            selected_index: 0,
-            filtered_indexes: Vec::new(),
-            state: TableState::default().with_selected(0),
+            bookmarks: model.query_bookmarks(),
+            layer_entries: model.query_layer_entries(&[])?,
+            layer_format: model.layer.format(),
+            column_aliases: model.column_aliases(),
+            state: TableState::default().with_selected(selected),
             scroll_state: scrollbar_state,
-            current_screen: CurrentScreen::Main(MainInput::None),
+            ..AppState::default()
         })
     }
 
-    pub fn refresh(&mut self, model: &Model) -> Result<()> {
-        self.items = model.query_protos()?;
+    pub fn refresh(&mut self, model: &mut Model, filter: &str) -> Result<()> {
+        if !model.is_connected() {
+            model.reconnect()?;
+        }
+        // views keep the order of their query
+        let sorted_by = self.sql_sort().filter(|_| self.active_view.is_none());
+        let items = match (&self.active_view, &sorted_by) {
+            (Some(view), _) => model.execute_custom_view(view, &self.view_parameters)?,
+            (None, Some(sort)) => model.query_protos_sorted(&self.sql_filter, sort)?,
+            (None, None) if self.sql_filter.is_active() => {
+                model.query_protos_filtered(&self.sql_filter)?
+            }
+            (None, None) => model.query_protos()?,
+        };
+        self.set_items(items, sorted_by, filter)
+    }
+
+    // the order SQLite should load the items in, capture order needing no sort
+    pub fn sql_sort(&self) -> Option<SortConfig> {
+        (self.sort_config != SortConfig::default()).then_some(self.sort_config)
+    }
+
+    // filters the new items, the selected proto staying selected while still listed.
+    // `sorted_by` is the order SQLite returned them in, if any
+    pub fn set_items(
+        &mut self,
+        items: Vec<Header>,
+        sorted_by: Option<SortConfig>,
+        filter: &str,
+    ) -> Result<()> {
+        let previous = self.selected_rowid();
+        self.items = items;
+        self.items_sorted_by = sorted_by;
+        // queried again the next time the session picker opens
+        self.session_counts.clear();
+        self.invalidate_filter();
+        self.filter(filter)?;
+
+        let items = &self.items;
+        let position = previous.and_then(|rowid| {
+            self.filtered_indexes
+                .iter()
+                .position(|i| items[*i].rowid == rowid)
+        });
+        match position {
+            Some(position) => self.update_state(position),
+            // the selected proto is gone, keep the selection where it was
+            None => {
+                if let Some(last) = self.filtered_indexes.len().checked_sub(1) {
+                    self.update_state(self.selected_index.min(last));
+                }
+            }
+        }
         Ok(())
     }
 
+    // every whitespace separated token must be found in `v`, those prefixed with `!` must not
     pub fn matches_filter(&self, v: &str, f: &str) -> bool {
-        v.contains(f)
+        f.split_whitespace()
+            .all(|token| match negated_token(token) {
+                Some(excluded) => !v.contains(excluded),
+                None => v.contains(token),
+            })
     }
 
     pub fn filter(&mut self, filter_value: &str) -> Result<()> {
-        if filter_value.is_empty() {
+        // called after every event, most of which leave the filter as it was
+        let applied = AppliedFilter {
+            value: filter_value.to_string(),
+            mode: self.filter_mode,
+            fuzzy_threshold: self.fuzzy_threshold,
+            bookmarks_only: self.bookmarks_only,
+            size_filter: self.size_filter,
+            sort_config: self.sort_config,
+        };
+        if self.last_applied_filter.as_ref() == Some(&applied) {
+            return Ok(());
+        }
+
+        let mut scored = false;
+        if self.bookmarks_only {
+            self.filtered_indexes = self
+                .items
+                .iter()
+                .enumerate()
+                .filter(|(_, h)| self.bookmarks.contains(&h.rowid))
+                .map(|(i, _)| i)
+                .collect();
+        } else if filter_value.is_empty() {
             self.filtered_indexes = (0..self.items.len()).collect();
-        } else {
+        } else if let Some(tag) = filter_value.strip_prefix('#') {
             self.filtered_indexes = self
                 .items
                 .iter()
                 .enumerate()
-                .filter(|(_, h)| self.matches_filter(&h.name, filter_value))
+                .filter(|(_, h)| h.tags.iter().any(|t| t == tag))
                 .map(|(i, _)| i)
                 .collect();
+        } else {
+            self.filtered_indexes = match self.filter_mode {
+                FilterMode::Exact => self
+                    .items
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, h)| self.matches_filter(&h.name, filter_value))
+                    .map(|(i, _)| i)
+                    .collect(),
+                FilterMode::Regex => self.regex_matches(filter_value),
+                FilterMode::Fuzzy => {
+                    scored = true;
+                    self.fuzzy_matches(filter_value)
+                }
+            };
 
             //self.update_state(new_state);
         }
+        if let Some((min, max)) = self.size_filter {
+            let items = &self.items;
+            self.filtered_indexes.retain(|i| {
+                items[*i]
+                    .data_size
+                    .is_some_and(|size| (min..=max).contains(&size))
+            });
+        }
+        self.sort_filtered(scored);
+        self.last_applied_filter = Some(applied);
+        // the selected row may now be another proto
+        self.last_queried_index = None;
+
+        Ok(())
+    }
+
+    // an invalid pattern, usually one still being typed, matches nothing
+    fn regex_matches(&self, pattern: &str) -> Vec<usize> {
+        let Ok(regex) = Regex::new(pattern) else {
+            return Vec::new();
+        };
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| regex.is_match(&h.name))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // best scores first, equal ones staying in capture order
+    fn fuzzy_matches(&self, pattern: &str) -> Vec<usize> {
+        let matcher = SkimMatcherV2::default();
+        let mut scored: Vec<(i64, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, h)| {
+                matcher
+                    .fuzzy_match(&h.name, pattern)
+                    .filter(|score| *score > self.fuzzy_threshold)
+                    .map(|score| (score, i))
+            })
+            .collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, i)| i).collect()
+    }
+
+    // the items or the bookmarks changed, the next `filter` call rebuilds the list
+    pub fn invalidate_filter(&mut self) {
+        self.last_applied_filter = None;
+    }
+
+    // `scored` when the filtered items are in fuzzy score order rather than in the
+    // order of `items`
+    fn sort_filtered(&mut self, scored: bool) {
+        // loaded in this order by SQLite
+        if self.items_sorted_by == Some(self.sort_config) && !scored {
+            return;
+        }
+        let items = &self.items;
+        match self.sort_config.key {
+            SortKey::RowId if self.items_sorted_by.is_some() && !scored => {
+                self.filtered_indexes.sort_by_key(|i| items[*i].rowid)
+            }
+            // items are already in capture order, or in score order in fuzzy mode
+            SortKey::RowId => {}
+            SortKey::Name => self
+                .filtered_indexes
+                .sort_by(|a, b| items[*a].name.cmp(&items[*b].name)),
+            SortKey::Timestamp => self
+                .filtered_indexes
+                .sort_by(|a, b| items[*a].timestamp.cmp(&items[*b].timestamp)),
+            SortKey::SessionId => self.filtered_indexes.sort_by_key(|i| items[*i].session_id),
+            SortKey::DataSize => self.filtered_indexes.sort_by_key(|i| items[*i].data_size),
+        }
+        if self.sort_config.descending {
+            self.filtered_indexes.reverse();
+        }
+    }
+
+    // sessions the picker lists with their proto count, by id. Protos without a
+    // session are left out, `SqlFilter` having no way to select them
+    pub fn picker_sessions(&self) -> Vec<(usize, usize)> {
+        let mut sessions: Vec<(usize, usize)> = self
+            .session_counts
+            .iter()
+            .filter_map(|(id, count)| id.map(|id| (id, *count)))
+            .collect();
+        sessions.sort_unstable();
+        sessions
+    }
+
+    pub fn compute_stats(&self) -> Stats {
+        let headers = self.get_filtered_data().unwrap_or_default();
+        let sizes: Vec<usize> = headers.iter().filter_map(|h| h.data_size).collect();
+        let sessions: HashSet<usize> = headers.iter().filter_map(|h| h.session_id).collect();
+
+        Stats {
+            total: self.items.len(),
+            filtered: headers.len(),
+            min_size: sizes.iter().min().copied(),
+            max_size: sizes.iter().max().copied(),
+            mean_size: (!sizes.is_empty())
+                .then(|| sizes.iter().sum::<usize>() as f64 / sizes.len() as f64),
+            // timestamps are ISO formatted, sorting them as strings is enough
+            earliest: headers.iter().map(|h| &h.timestamp).min().cloned(),
+            latest: headers.iter().map(|h| &h.timestamp).max().cloned(),
+            sessions: sessions.len(),
+        }
+    }
+
+    // message counts of the filtered protos over `buckets` equal slices of their time
+    // range, protos with an unparsable timestamp being ignored
+    pub fn bucket_by_time(&self, buckets: usize) -> Vec<u64> {
+        let mut counts = vec![0; buckets];
+        let times: Vec<i64> = self
+            .get_filtered_data()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|h| parse_timestamp(&h.timestamp))
+            .collect();
+        let (Some(first), Some(last)) = (times.iter().min(), times.iter().max()) else {
+            return counts;
+        };
+
+        let range = (last - first + 1) as f64;
+        for time in &times {
+            let bucket = ((time - first) as f64 / range * buckets as f64) as usize;
+            counts[bucket.min(buckets - 1)] += 1;
+        }
+        counts
+    }
+
+    pub fn ui_state(&self, filter: &str) -> UiState {
+        UiState {
+            filter: filter.to_owned(),
+            selected_index: self.selected_index,
+            scroll_y: self.state.offset().try_into().unwrap_or(u16::MAX),
+            sort_config: self.sort_config,
+            wrap_navigation: self.wrap_navigation,
+            split_percent: self.layout.split_percent,
+            filter_history: Vec::new(), // owned by the `InputArena` too
+        }
+    }
 
+    // the filter input itself is owned by the `InputArena`, only the list is filtered here
+    pub fn apply_ui_state(&mut self, ui_state: &UiState) -> Result<()> {
+        self.sort_config = ui_state.sort_config;
+        self.wrap_navigation = ui_state.wrap_navigation;
+        self.layout.split_percent = ui_state
+            .split_percent
+            .clamp(*SPLIT_PERCENT.start(), *SPLIT_PERCENT.end());
+        self.filter(&ui_state.filter)?;
+        if self.filtered_indexes.is_empty() {
+            return Ok(());
+        }
+        self.update_state(ui_state.selected_index.min(self.filtered_indexes.len() - 1));
+        *self.state.offset_mut() = ui_state.scroll_y.into();
         Ok(())
     }
 
+    pub fn toggle_bookmark(&mut self, model: &mut Model) -> Result<()> {
+        let Some(rowid) = self.selected_rowid() else {
+            return Ok(());
+        };
+        if !self.bookmarks.remove(&rowid) {
+            self.bookmarks.insert(rowid);
+        }
+        self.invalidate_filter();
+        model.set_bookmarks(&self.bookmarks)
+    }
+
+    pub fn toggle_multi_selection(&mut self) {
+        let Some(rowid) = self.selected_rowid() else {
+            return;
+        };
+        if let Some(position) = self.multi_selection.iter().position(|r| *r == rowid) {
+            self.multi_selection.remove(position);
+        } else {
+            self.multi_selection.push(rowid);
+        }
+    }
+
+    // the full name of the proto drawn at `row` when it was cut to fit its column,
+    // the tooltip going one line below the mouse. Row heights must match the rows
+    // built by `ui::build_table`
+    pub fn tooltip_at(&self, column: u16, row: u16) -> Option<(u16, u16, String)> {
+        let area = self.table_area;
+        let name_width = self.name_width?;
+        // the first line being the table header
+        if !area.contains(Position::new(column, row)) || row == area.y {
+            return None;
+        }
+        let mut y = area.y + 1;
+        for header in self
+            .get_filtered_data()
+            .ok()?
+            .into_iter()
+            .skip(self.state.offset())
+        {
+            let annotated = header.annotation.as_ref().is_some_and(|a| !a.is_empty());
+            y += if annotated { 2 } else { 1 };
+            if row < y {
+                let bookmark = if self.bookmarks.contains(&header.rowid) {
+                    2
+                } else {
+                    0
+                };
+                let cut = header.display_name(name_width.saturating_sub(bookmark));
+                return matches!(cut, Cow::Owned(_))
+                    .then(|| (column, row + 1, header.name.clone()));
+            }
+        }
+        None
+    }
+
     pub fn get_filtered_data(&self) -> Result<Vec<&Header>> {
         Ok(self
             .filtered_indexes
@@ -78,30 +803,152 @@ impl AppState {
             .collect())
     }
 
-    pub fn get_data(&mut self, model: &Model) -> Result<()> {
-        if self.filtered_indexes.is_empty() {
-            self.cached = None;
-        } else {
-            let real_index = self
-                .filtered_indexes
-                .get(self.selected_index)
-                .unwrap_or(self.filtered_indexes.last().unwrap());
-            let item = self
-                .items
-                .get(*real_index)
-                .context(format!("Cannot find item from index {0}", real_index))?;
-
-            if let Some((cached_index, _)) = &self.cached {
-                if item.rowid == *cached_index {
-                    return Ok(());
+    // widest cell of every visible column, header included, shrunk to fit in `pane_width`
+    pub fn column_widths(&self, pane_width: u16) -> Result<Vec<Constraint>> {
+        let headers = self.get_filtered_data()?;
+        let mut widths: Vec<usize> = self
+            .visible_columns
+            .iter()
+            .map(|column| {
+                let (db_column, name, key) = column_header(*column);
+                let name = self
+                    .column_aliases
+                    .get(db_column)
+                    .map_or(name, String::as_str);
+                // room for the sort arrow
+                let arrow = if key == self.sort_config.key { 2 } else { 0 };
+                let content = headers
+                    .iter()
+                    .map(|h| self.cell_width(h, *column))
+                    .max()
+                    .unwrap_or(0);
+                let width = (name.width() + arrow).max(content) + 2;
+                match column {
+                    ColumnId::Name => width.max(15),
+                    _ => width,
                 }
+            })
+            .collect();
+
+        // the highlight symbol, the scrollbar and the spacing between columns
+        let available = usize::from(pane_width)
+            .saturating_sub(HIGHLIGHT_SYMBOL.width() + 1 + widths.len().saturating_sub(1));
+        let total: usize = widths.iter().sum();
+        if total > available {
+            widths
+                .iter_mut()
+                .for_each(|width| *width = *width * available / total);
+        }
+
+        Ok(widths
+            .into_iter()
+            .map(|width| Constraint::Min(width.try_into().unwrap_or(u16::MAX)))
+            .collect())
+    }
+
+    // must match the cells built by `ui::build_table`
+    fn cell_width(&self, header: &Header, column: ColumnId) -> usize {
+        match column {
+            ColumnId::Name => {
+                let bookmark = if self.bookmarks.contains(&header.rowid) {
+                    2
+                } else {
+                    0
+                };
+                // tags are drawn as ` tag ` badges after a space
+                let tags: usize = header.tags.iter().map(|tag| tag.width() + 3).sum();
+                let annotation = header.annotation.as_deref().map_or(0, str::width);
+                (bookmark + header.name.width() + tags).max(annotation)
             }
+            ColumnId::Session => header
+                .session_id
+                .map_or(4, |session| session.to_string().width()),
+            ColumnId::Timestamp => header.timestamp.width(),
+            ColumnId::RowId => header.rowid.to_string().width(),
+            ColumnId::DataSize => header.data_size.map_or(0, |size| format_size(size).width()),
+            ColumnId::Data => 0,
+        }
+    }
+
+    pub fn push_toast(&mut self, message: &str, duration: Duration, style: Style) {
+        self.toasts.push_back(Toast {
+            message: message.to_owned(),
+            expires_at: Instant::now() + duration,
+            style,
+        });
+    }
+
+    pub fn expire_toasts(&mut self) {
+        let now = Instant::now();
+        self.toasts.retain(|toast| toast.expires_at >= now);
+    }
+
+    pub fn get_filtered_count(&self) -> usize {
+        self.filtered_indexes.len()
+    }
+
+    pub fn get_total_count(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn selected_header(&self) -> Option<&Header> {
+        let selected = self.state.selected()?;
+        let real_index = self
+            .filtered_indexes
+            .get(selected)
+            .or(self.filtered_indexes.last())?;
+        self.items.get(*real_index)
+    }
+
+    pub fn selected_rowid(&self) -> Option<usize> {
+        self.selected_header().map(|h| h.rowid)
+    }
 
-            self.cached = Some((item.rowid, model.query_data(&item.rowid)?));
+    pub fn get_data(&mut self, model: &mut Model) -> Result<()> {
+        let selected = self.state.selected();
+        // same row of the same list, nothing to look up
+        if selected.is_some() && selected == self.last_queried_index {
+            return Ok(());
         }
+
+        let Some(header) = self.selected_header().cloned() else {
+            self.cached = None;
+            return Ok(());
+        };
+        // the header may have been annotated or tagged meanwhile, not its data
+        if let Some((cached_header, _)) = &mut self.cached {
+            if header.rowid == cached_header.rowid {
+                *cached_header = header;
+                self.last_queried_index = selected;
+                return Ok(());
+            }
+        }
+
+        let data = model
+            .query_data_bytes(&header.rowid)
+            .with_context(|| format!("Cannot read the data of `{header}`"))?;
+        self.cached = Some((header, data));
+        self.last_queried_index = selected;
         Ok(())
     }
 
+    // scrolls the proto table so the selected row is within `visible_rows`
+    pub fn keep_selection_visible(&mut self) {
+        let Some(selected) = self.state.selected() else {
+            *self.state.offset_mut() = 0;
+            return;
+        };
+        let first = selected.saturating_sub(self.visible_rows.saturating_sub(1));
+        let offset = self.state.offset().clamp(first, selected);
+        *self.state.offset_mut() = offset;
+    }
+
+    pub fn deselect(&mut self) {
+        self.selected_index = 0;
+        self.state.select(None);
+        self.scroll_state = self.scroll_state.position(0);
+    }
+
     pub fn update_state(&mut self, new_state: usize) {
         self.selected_index = new_state;
         self.state.select(Some(new_state));
@@ -109,12 +956,18 @@ impl AppState {
     }
 
     pub fn next_row(&mut self) -> Result<()> {
+        if self.filtered_indexes.is_empty() {
+            return Ok(());
+        }
+
         let i = match self.state.selected() {
             Some(i) => {
-                if i >= self.filtered_indexes.len() - 1 {
+                if i < self.filtered_indexes.len() - 1 {
+                    i + 1
+                } else if self.wrap_navigation {
                     0
                 } else {
-                    i + 1
+                    return Ok(());
                 }
             }
             None => 0,
@@ -126,12 +979,18 @@ impl AppState {
     }
 
     pub fn previous_row(&mut self) -> Result<()> {
+        if self.filtered_indexes.is_empty() {
+            return Ok(());
+        }
+
         let i = match self.state.selected() {
             Some(i) => {
-                if i == 0 {
+                if i > 0 {
+                    i - 1
+                } else if self.wrap_navigation {
                     self.filtered_indexes.len() - 1
                 } else {
-                    i - 1
+                    return Ok(());
                 }
             }
             None => 0,
@@ -141,63 +1000,445 @@ impl AppState {
 
         Ok(())
     }
-}
 
-pub struct App {
-    pub model: Model, // file and sqlite db manipulation
-    pub state: AppState,
-    pub input_arena: InputArena,
-    pub exit: bool, // used to terminate the program
-}
+    pub fn next_page(&mut self) -> Result<()> {
+        if self.filtered_indexes.is_empty() {
+            return Ok(());
+        }
 
-impl App {
-    pub fn new(db_path: std::path::PathBuf, layer_path: std::path::PathBuf) -> Result<Self> {
-        let model = Model::new(&db_path, layer_path)?;
-        let state = AppState::new(&model)?;
+        let last = self.filtered_indexes.len() - 1;
+        let i = match self.state.selected() {
+            Some(i) => (i + self.page_size).min(last),
+            None => 0,
+        };
 
-        Ok(App {
-            model,
-            state,
-            input_arena: InputArena::new()?,
-            exit: false,
-        })
+        self.update_state(i);
+
+        Ok(())
     }
 
-    pub fn toggle_editing(&mut self) {
-        if let CurrentScreen::Editing(focused) = &self.state.current_screen {
-            match focused {
-                EditingInput::Key => {
-                    self.state.current_screen = CurrentScreen::Editing(EditingInput::Value)
-                }
-                EditingInput::Value => {
-                    self.state.current_screen = CurrentScreen::Editing(EditingInput::Key)
+    pub fn previous_page(&mut self) -> Result<()> {
+        if self.filtered_indexes.is_empty() {
+            return Ok(());
+        }
+
+        let i = match self.state.selected() {
+            Some(i) => i.saturating_sub(self.page_size),
+            None => 0,
+        };
+
+        self.update_state(i);
+
+        Ok(())
+    }
+
+    // the opened table may be gone after a reload or a merge, the deepest table left
+    // being shown instead, with the cursor on the key that led to the missing one
+    pub fn load_layer_entries(&mut self, model: &Model) -> Result<()> {
+        let mut gone = None;
+        loop {
+            let path: Vec<&str> = self.layer_nav_path.iter().map(String::as_str).collect();
+            match model.query_layer_entries(&path) {
+                Ok(entries) => {
+                    self.layer_entries = entries;
+                    break;
                 }
-            };
-        } else {
-            self.state.current_screen = CurrentScreen::Editing(EditingInput::Value);
+                Err(_) if !self.layer_nav_path.is_empty() => gone = self.layer_nav_path.pop(),
+                Err(err) => return Err(err),
+            }
+        }
+        if let Some(key) = gone {
+            let i = self
+                .layer_entries
+                .iter()
+                .position(|e| e.key == key)
+                .unwrap_or(0);
+            self.layer_state.select(Some(i));
         }
+        Ok(())
     }
 
-    fn handle_key_event_main_screen(
-        &mut self,
+    pub fn next_layer_row(&mut self) {
+        let i = match self.layer_state.selected() {
+            Some(i) if i + 1 < self.layer_entries.len() => i + 1,
+            _ => 0,
+        };
+        self.layer_state.select(Some(i));
+    }
+
+    pub fn previous_layer_row(&mut self) {
+        let i = match self.layer_state.selected() {
+            Some(0) | None => self.layer_entries.len().saturating_sub(1),
+            Some(i) => i - 1,
+        };
+        self.layer_state.select(Some(i));
+    }
+
+    pub fn enter_layer_table(&mut self, model: &Model) -> Result<()> {
+        let Some(entry) = self
+            .layer_state
+            .selected()
+            .and_then(|i| self.layer_entries.get(i))
+        else {
+            return Ok(());
+        };
+
+        if entry.is_table {
+            self.layer_nav_path.push(entry.key.clone());
+            self.load_layer_entries(model)?;
+            self.layer_state.select(Some(0));
+        }
+
+        Ok(())
+    }
+
+    // back to the table at `depth` levels below the root, the root being 0
+    pub fn navigate_layer_to(&mut self, depth: usize, model: &Model) -> Result<()> {
+        if depth >= self.layer_nav_path.len() {
+            return Ok(());
+        }
+        let key = self.layer_nav_path.drain(depth..).next();
+        self.load_layer_entries(model)?;
+        // put the cursor on the table we came from
+        let i = self
+            .layer_entries
+            .iter()
+            .position(|e| Some(&e.key) == key.as_ref())
+            .unwrap_or(0);
+        self.layer_state.select(Some(i));
+        Ok(())
+    }
+
+    // level of the breadcrumb segment drawn at column `x`, if any
+    pub fn breadcrumb_depth_at(&self, x: u16) -> Option<usize> {
+        let mut end = self.breadcrumb_area.x;
+        for (depth, segment) in breadcrumb_segments(&self.layer_nav_path).iter().enumerate() {
+            end = end.saturating_add(segment.width().try_into().unwrap_or(u16::MAX));
+            if x < end {
+                return Some(depth);
+            }
+        }
+        None
+    }
+
+    pub fn leave_layer_table(&mut self, model: &Model) -> Result<()> {
+        self.navigate_layer_to(self.layer_nav_path.len().saturating_sub(1), model)
+    }
+}
+
+#[derive(Default)]
+pub struct AppBuilder {
+    config: Config,
+}
+
+impl AppBuilder {
+    pub fn db_path(mut self, db_path: std::path::PathBuf) -> Self {
+        self.config.db_path = db_path;
+        self
+    }
+
+    pub fn layer_path(mut self, layer_path: std::path::PathBuf) -> Self {
+        self.config.layer_path = layer_path;
+        self
+    }
+
+    pub fn layer_format(mut self, layer_format: Option<LayerFormat>) -> Self {
+        self.config.layer_format = layer_format;
+        self
+    }
+
+    pub fn read_write(mut self, read_write: bool) -> Self {
+        self.config.read_write = read_write;
+        self
+    }
+
+    pub fn create_layer(mut self, create_layer: bool) -> Self {
+        self.config.create_layer = create_layer;
+        self
+    }
+
+    pub fn cache_size(mut self, cache_size: std::num::NonZeroUsize) -> Self {
+        self.config.cache_size = cache_size;
+        self
+    }
+
+    pub fn fuzzy_threshold(mut self, fuzzy_threshold: i64) -> Self {
+        self.config.fuzzy_threshold = fuzzy_threshold;
+        self
+    }
+
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.config.page_size = page_size;
+        self
+    }
+
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.config.theme = theme;
+        self
+    }
+
+    pub fn keybindings(mut self, keybindings: KeyBindings) -> Self {
+        self.config.keybindings = keybindings;
+        self
+    }
+
+    pub fn tick_rate(mut self, tick_rate: u64) -> Self {
+        self.config.tick_rate = tick_rate;
+        self
+    }
+
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.config.fps = fps;
+        self
+    }
+
+    pub fn mouse(mut self, mouse: bool) -> Self {
+        self.config.mouse = mouse;
+        self
+    }
+
+    pub fn build(self) -> Result<App> {
+        let config = self.config;
+        let layer_format = config
+            .layer_format
+            .unwrap_or_else(|| LayerFormat::from_path(&config.layer_path));
+        let file_name = |path: &Path| {
+            path.file_name().map_or_else(
+                || path.display().to_string(),
+                |n| n.to_string_lossy().into_owned(),
+            )
+        };
+        let db_name = file_name(&config.db_path);
+        let layer_name = file_name(&config.layer_path);
+        if config.create_layer && !config.layer_path.exists() {
+            Model::create_layer(&config.layer_path, layer_format)?;
+        }
+        let mut model = Model::new(
+            &config.db_path,
+            config.layer_path,
+            layer_format,
+            config.read_write,
+        )?;
+        model.set_cache_size(config.cache_size);
+        let mut state = AppState::new(&mut model)?;
+        state.theme = config.theme;
+        state.keybindings = config.keybindings;
+        state.db_name = db_name;
+        state.layer_name = layer_name;
+        state.page_size = config.page_size;
+        state.fuzzy_threshold = config.fuzzy_threshold;
+        if let Ok((_, rows)) = ratatui::crossterm::terminal::size() {
+            state.visible_rows = state.layout.visible_rows(rows);
+        }
+
+        let mut input_arena = InputArena::new();
+        let ui_state_path = UiState::path(&config.db_path);
+        // a missing or unreadable UI state only means starting from the top
+        if let Some(ui_state) = ui_state_path
+            .as_deref()
+            .and_then(|path| UiState::load(path).ok())
+        {
+            let filter = MainInput::Filter.try_into()?;
+            for value in ui_state.filter.chars() {
+                input_arena.value_push(&filter, value)?;
+            }
+            input_arena
+                .get_mut(&filter)?
+                .set_history(ui_state.filter_history.clone());
+            state.apply_ui_state(&ui_state)?;
+        }
+
+        Ok(App {
+            model,
+            state,
+            input_arena,
+            ui_state_path,
+            tick_rate: config.tick_rate,
+            fps: config.fps,
+            mouse: config.mouse,
+            pending_save: None,
+            last_save: None,
+            exit: false,
+            refreshing: None,
+        })
+    }
+}
+
+// headers read by a background query, with the order SQLite sorted them in
+type PendingRefresh = (Receiver<Result<Vec<Header>>>, Option<SortConfig>);
+
+pub struct App {
+    pub model: Model, // file and sqlite db manipulation
+    pub state: AppState,
+    pub input_arena: InputArena,
+    pub ui_state_path: Option<PathBuf>, // where the list position is kept between runs
+    pub tick_rate: u64,                 // in milliseconds
+    pub fps: u32,                       // most frames drawn per second
+    pub mouse: bool,                    // whether mouse events are handled
+    pub pending_save: Option<Instant>,  // when the layer was last modified without being saved
+    pub last_save: Option<Instant>,     // when the layer was last auto-saved
+    pub exit: bool,                     // used to terminate the program
+    pub refreshing: Option<PendingRefresh>, // items read by a background query
+}
+
+impl App {
+    pub fn save_ui_state(&self) -> Result<()> {
+        let Some(path) = &self.ui_state_path else {
+            return Ok(());
+        };
+        let filter = MainInput::Filter.try_into()?;
+        UiState {
+            filter_history: self.input_arena.get(&filter)?.history().to_vec(),
+            ..self.state.ui_state(self.input_arena.get_content(&filter)?)
+        }
+        .save(path)
+    }
+
+    pub fn toggle_editing(&mut self) {
+        if let CurrentScreen::Editing(focused) = &self.state.current_screen {
+            match focused {
+                EditingInput::Key => {
+                    self.state.current_screen = CurrentScreen::Editing(EditingInput::Value)
+                }
+                EditingInput::Value => {
+                    self.state.current_screen = CurrentScreen::Editing(EditingInput::Key)
+                }
+            };
+        } else {
+            self.state.current_screen = CurrentScreen::Editing(EditingInput::Value);
+        }
+    }
+
+    fn handle_key_event_main_screen(
+        &mut self,
         key_event: KeyEvent,
         focused: &MainInput,
     ) -> Result<()> {
         match focused {
             MainInput::None => {
+                if key_event.code != KeyCode::Esc {
+                    self.state.deselect_count = 0;
+                }
                 match key_event.code {
-                    KeyCode::Char('e') => {
+                    // before the filter keybinding, `f` by default
+                    KeyCode::Char('f') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.state.filter_mode = self.state.filter_mode.next()
+                    }
+                    code if code == self.state.keybindings.edit => {
+                        self.state.edit_error = None;
+                        let path: Vec<&str> = self
+                            .state
+                            .layer_nav_path
+                            .iter()
+                            .map(String::as_str)
+                            .collect();
+                        self.state.layer_key_candidates = self.model.layer_keys(&path);
                         self.state.current_screen = CurrentScreen::Editing(EditingInput::Key);
                     }
-                    KeyCode::Char('q') => {
-                        self.state.current_screen = CurrentScreen::Exiting;
+                    code if code == self.state.keybindings.quit => {
+                        self.state.current_screen = CurrentScreen::Exiting {
+                            dirty: self.pending_save.is_some() || self.model.is_dirty(),
+                        };
                     }
-                    KeyCode::Char('f') => {
+                    code if code == self.state.keybindings.filter => {
                         self.state.current_screen = CurrentScreen::Main(MainInput::Filter)
                     }
+                    code if code == self.state.keybindings.refresh => self.start_refresh()?,
                     KeyCode::Down => self.state.next_row()?,
                     KeyCode::Up => self.state.previous_row()?,
-                    KeyCode::Char('r') => self.state.refresh(&self.model)?,
+                    KeyCode::PageDown => self.state.next_page()?,
+                    KeyCode::PageUp => self.state.previous_page()?,
+                    KeyCode::Tab => {
+                        self.state.current_screen = CurrentScreen::Main(MainInput::Layer)
+                    }
+                    KeyCode::Char('d') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.open_schema_view()?
+                    }
+                    KeyCode::Char('w') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.state.wrap_navigation = !self.state.wrap_navigation
+                    }
+                    KeyCode::Char('a') => self.open_annotation()?,
+                    KeyCode::Delete => self.open_delete_proto(),
+                    KeyCode::Char('n') => self.open_add_proto()?,
+                    // Ctrl+D being the schema view, C as in copy
+                    KeyCode::Char('C') => self.duplicate_selected_proto()?,
+                    KeyCode::Char('Q') => {
+                        self.state.sql_error = None;
+                        self.state.current_screen = CurrentScreen::SqlQuery;
+                    }
+                    KeyCode::Esc if self.state.sql_result.is_some() => self.state.sql_result = None,
+                    KeyCode::Esc => self.undo_selection_step()?,
+                    KeyCode::Char('b') => {
+                        self.state.toggle_bookmark(&mut self.model)?;
+                        // saved by on_tick like any other layer change
+                        self.pending_save = Some(Instant::now());
+                    }
+                    KeyCode::Char('B') => self.state.bookmarks_only = !self.state.bookmarks_only,
+                    KeyCode::Char('v') => {
+                        self.state.views = self.model.list_views();
+                        self.state.view_selected = 0;
+                        self.state.current_screen = CurrentScreen::ViewPicker;
+                    }
+                    KeyCode::Char('z') => self.open_size_filter()?,
+                    KeyCode::Char('g') => self.open_session_picker()?,
+                    KeyCode::Char('i') => self.state.current_screen = CurrentScreen::Statistics,
+                    KeyCode::Char('m') => {
+                        self.state.data_view_mode = self.state.data_view_mode.next()
+                    }
+                    KeyCode::Char('s') => {
+                        self.state.sort_config.key = self.state.sort_config.key.next()
+                    }
+                    KeyCode::Char('S') => {
+                        self.state.sort_config.descending = !self.state.sort_config.descending
+                    }
+                    KeyCode::Char(' ') => self.state.toggle_multi_selection(),
+                    KeyCode::Char('<') => {
+                        self.state.layout.split_percent = (self.state.layout.split_percent
+                            - SPLIT_STEP)
+                            .max(*SPLIT_PERCENT.start())
+                    }
+                    KeyCode::Char('>') => {
+                        self.state.layout.split_percent =
+                            (self.state.layout.split_percent + SPLIT_STEP).min(*SPLIT_PERCENT.end())
+                    }
+                    KeyCode::Char('D') => self.open_diff()?,
+                    KeyCode::Char(':') => self.open_command("")?,
+                    KeyCode::Char('x') => self.open_command("export ")?,
+                    _ => {}
+                };
+            }
+            MainInput::Layer => {
+                match key_event.code {
+                    KeyCode::Down => self.state.next_layer_row(),
+                    KeyCode::Up => self.state.previous_layer_row(),
+                    KeyCode::Char('l') | KeyCode::Enter => {
+                        self.state.enter_layer_table(&self.model)?
+                    }
+                    KeyCode::Char('h') | KeyCode::Backspace => {
+                        self.state.leave_layer_table(&self.model)?
+                    }
+                    KeyCode::Char('J') => {
+                        self.state.layer_json = match self.state.layer_json {
+                            Some(_) => None,
+                            None => Some(self.model.layer_as_json()?),
+                        }
+                    }
+                    // the JSON view is read only
+                    KeyCode::Char('d') if self.state.layer_json.is_none() => {
+                        if let Some(entry) = self
+                            .state
+                            .layer_state
+                            .selected()
+                            .and_then(|i| self.state.layer_entries.get(i))
+                        {
+                            let mut path = self.state.layer_nav_path.clone();
+                            path.push(entry.key.clone());
+                            self.state.current_screen = CurrentScreen::ConfirmDelete { path };
+                        }
+                    }
+                    KeyCode::Tab | KeyCode::Esc => {
+                        self.state.current_screen = CurrentScreen::Main(MainInput::None)
+                    }
                     _ => {}
                 };
             }
@@ -206,12 +1447,50 @@ impl App {
                     KeyCode::Backspace => {
                         self.input_arena.value_pop(focused.try_into()?)?;
                     }
-                    KeyCode::Enter | KeyCode::Esc => {
+                    KeyCode::Enter => {
+                        self.input_arena
+                            .get_mut(focused.try_into()?)?
+                            .push_history();
+                        self.state.current_screen = CurrentScreen::Main(MainInput::None)
+                    }
+                    KeyCode::Esc => {
                         self.state.current_screen = CurrentScreen::Main(MainInput::None)
                     }
-                    KeyCode::Char(value) => {
-                        self.input_arena.value_push(focused.try_into()?, value)?;
+                    KeyCode::Up => self
+                        .input_arena
+                        .get_mut(focused.try_into()?)?
+                        .history_previous(),
+                    KeyCode::Down => self
+                        .input_arena
+                        .get_mut(focused.try_into()?)?
+                        .history_next(),
+                    KeyCode::Char('f') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.state.filter_mode = self.state.filter_mode.next()
+                    }
+                    KeyCode::Char(value) => self.push_limited(focused.try_into()?, value)?,
+                    _ => {}
+                };
+            }
+            MainInput::Command => {
+                match key_event.code {
+                    KeyCode::Backspace => {
+                        self.input_arena.value_pop(focused.try_into()?)?;
+                    }
+                    KeyCode::Enter => {
+                        let line = self.input_arena.get_content(focused.try_into()?)?.clone();
+                        self.input_arena.reset_field(focused.try_into()?)?;
+                        // commands may open another screen
+                        self.state.current_screen = CurrentScreen::Main(MainInput::None);
+                        self.state.command_message = match self.run_command(&line) {
+                            Ok(message) => message,
+                            Err(err) => Some(format!("{err:#}")),
+                        };
+                    }
+                    KeyCode::Esc => {
+                        self.input_arena.reset_field(focused.try_into()?)?;
+                        self.state.current_screen = CurrentScreen::Main(MainInput::None)
                     }
+                    KeyCode::Char(value) => self.push_limited(focused.try_into()?, value)?,
                     _ => {}
                 };
             }
@@ -220,11 +1499,157 @@ impl App {
         Ok(())
     }
 
-    fn handle_key_event_exit_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+    // each Escape in a row goes one step further: the row, the marked rows, then the filter
+    fn undo_selection_step(&mut self) -> Result<()> {
+        match self.state.deselect_count {
+            0 => self.state.deselect(),
+            1 => self.state.multi_selection.clear(),
+            _ => self.input_arena.reset_field(&InputId::Filter)?,
+        }
+        self.state.deselect_count = self.state.deselect_count.saturating_add(1);
+        Ok(())
+    }
+
+    // typing a character not fitting in the field flashes its border instead
+    fn push_limited(&mut self, k: &InputId, value: char) -> Result<()> {
+        if !self.input_arena.fits(k, value)? {
+            self.state
+                .input_overflow_flash
+                .insert(k.clone(), Instant::now());
+            return Ok(());
+        }
+        self.input_arena.value_push(k, value)
+    }
+
+    fn open_command(&mut self, prefill: &str) -> Result<()> {
+        let command = MainInput::Command.try_into()?;
+        self.input_arena.reset_field(&command)?;
+        for value in prefill.chars() {
+            self.input_arena.value_push(&command, value)?;
+        }
+        self.state.command_message = None;
+        self.state.current_screen = CurrentScreen::Main(MainInput::Command);
+        Ok(())
+    }
+
+    // runs a command palette line, returning the message to show in the footer
+    fn run_command(&mut self, line: &str) -> Result<Option<String>> {
+        let mut args = line.split_whitespace();
+        match args.next() {
+            Some("export") => {
+                let path = args.next().context("Usage: export <path> [columns]")?;
+                let columns = match args.next() {
+                    Some(list) => list.split(',').map(str::parse).collect::<Result<_>>()?,
+                    None => ColumnId::ALL.to_vec(),
+                };
+                let headers = self.state.get_filtered_data()?;
+                let written =
+                    self.model
+                        .export_filtered_csv(&headers, &columns, Path::new(path))?;
+                Ok(Some(format!("Exported {written} rows to {path}")))
+            }
+            Some("import") => {
+                let path = args.next().context("Usage: import <path>")?;
+                let processed = self.model.import_annotations(Path::new(path))?;
+                self.refresh_items()?;
+                Ok(Some(format!(
+                    "Imported {processed} annotations from {path}"
+                )))
+            }
+            Some("merge") => {
+                let path = args.next().context("Usage: merge <path> [strategy]")?;
+                let strategy = args.next().unwrap_or("interactive").parse()?;
+                self.state.merge_conflicts = self.model.merge_layer(Path::new(path), strategy)?;
+                // saved by on_tick like any other layer change
+                self.pending_save = Some(Instant::now());
+                self.show_next_conflict();
+                Ok(None)
+            }
+            Some("where") => {
+                let conditions = line.trim_start().strip_prefix("where").unwrap_or_default();
+                let filter = conditions.parse()?;
+                let previous = std::mem::replace(&mut self.state.sql_filter, filter);
+                // a failing query leaves the list as it was
+                if let Err(err) = self.refresh_items() {
+                    self.state.sql_filter = previous;
+                    return Err(err);
+                }
+                self.state.update_state(0);
+                Ok(Some(format!("{} protos loaded", self.state.items.len())))
+            }
+            Some("layout") => {
+                let preset = args.next().context("Usage: layout <preset>")?;
+                self.state.layout = preset.parse()?;
+                if let Ok((_, rows)) = ratatui::crossterm::terminal::size() {
+                    self.state.visible_rows = self.state.layout.visible_rows(rows);
+                }
+                Ok(None)
+            }
+            Some("export-json") => {
+                let path = args.next().context("Usage: export-json <path>")?;
+                self.model.export_layer_json(Path::new(path))?;
+                Ok(Some(format!("Exported the layer to {path}")))
+            }
+            Some("import-json") => {
+                let path = args.next().context("Usage: import-json <path>")?;
+                self.model.import_layer_json(Path::new(path))?;
+                // saved by on_tick like any other layer change
+                self.pending_save = Some(Instant::now());
+                self.state.bookmarks = self.model.query_bookmarks();
+                self.state.invalidate_filter();
+                self.state.column_aliases = self.model.column_aliases();
+                Ok(Some(format!(
+                    "Imported the keys missing from the layer from {path}"
+                )))
+            }
+            Some(command @ ("tag" | "untag")) => {
+                let tag = args.next().context(format!("Usage: {command} <tag>"))?;
+                let rowid = self.state.selected_rowid().context("No proto selected")?;
+                if command == "tag" {
+                    self.model.add_tag(rowid, tag)?;
+                } else {
+                    self.model.remove_tag(rowid, tag)?;
+                }
+                self.refresh_items()?;
+                Ok(None)
+            }
+            Some("columns") => {
+                let list = args.next().context("Usage: columns <col,...>")?;
+                let columns = list
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<Vec<ColumnId>>>()?;
+                if columns.contains(&ColumnId::Data) {
+                    return Err(Error::msg("The data column is only shown in the data pane"));
+                }
+                self.state.visible_columns = columns;
+                Ok(None)
+            }
+            Some("clear_filter_history") => {
+                self.input_arena
+                    .get_mut(&MainInput::Filter.try_into()?)?
+                    .clear_history();
+                Ok(Some(String::from("Filter history cleared")))
+            }
+            Some("tags") => Ok(Some(format!(
+                "Tags: {}",
+                self.model.query_unique_tags()?.join(", ")
+            ))),
+            Some(command) => Err(Error::msg(format!("Unknown command `{command}`"))),
+            None => Ok(None),
+        }
+    }
+
+    fn handle_key_event_exit_screen(&mut self, key_event: KeyEvent, dirty: bool) -> Result<()> {
         match key_event.code {
             KeyCode::Char('y') => {
+                self.pending_save = None;
+                if dirty {
+                    self.model.save_layer()?;
+                }
                 self.exit = true;
             }
+            KeyCode::Char('d') if dirty => self.exit = true,
             KeyCode::Char('n') | KeyCode::Char('q') => {
                 self.state.current_screen = CurrentScreen::Main(MainInput::Filter);
             }
@@ -234,54 +1659,1783 @@ impl App {
         Ok(())
     }
 
-    fn handle_key_event_edit_screen(
+    fn complete_editing(&mut self) -> Result<()> {
+        // the saved layer must match `previous` so the change can be discarded by reloading it
+        self.flush_pending_save()?;
+
+        let key = self.input_arena.get_content(&EditingInput::Key.into())?;
+        let raw = self.input_arena.get_content(&EditingInput::Value.into())?;
+        let previous = self.model.layer.clone();
+
+        match self.state.value_type.parse(raw).and_then(|item| {
+            // inside the table opened in the layer pane
+            let path: Vec<&str> = self
+                .state
+                .layer_nav_path
+                .iter()
+                .map(String::as_str)
+                .chain([key.as_str()])
+                .collect();
+            self.model.set_layer_key_at_path(&path, item)
+        }) {
+            Ok(()) => {
+                self.input_arena.reset_all(&EDIT_FIELDS)?;
+                self.state.edit_error = None;
+                self.state.diff_scroll = 0;
+                self.state.current_screen = CurrentScreen::ConfirmSave {
+                    diff: Model::layer_diff(&previous, &self.model.layer)?,
+                };
+            }
+            Err(err) => self.state.edit_error = Some(format!("{err:#}")),
+        }
+
+        Ok(())
+    }
+
+    fn open_annotation(&mut self) -> Result<()> {
+        let Some(rowid) = self.state.selected_rowid() else {
+            return Ok(());
+        };
+        if !self.model.is_writable() {
+            self.state
+                .push_toast(READ_ONLY_MESSAGE, TOAST_DURATION, ERROR_TOAST_STYLE);
+            return Ok(());
+        }
+        self.input_arena.reset_field(&InputId::Annotation)?;
+        for value in self
+            .model
+            .query_annotation(rowid)?
+            .unwrap_or_default()
+            .chars()
+        {
+            self.input_arena.value_push(&InputId::Annotation, value)?;
+        }
+        self.state.current_screen = CurrentScreen::Annotating { rowid };
+        Ok(())
+    }
+
+    fn handle_key_event_annotation_screen(
         &mut self,
         key_event: KeyEvent,
-        focused: &EditingInput,
+        rowid: usize,
     ) -> Result<()> {
         match key_event.code {
-            KeyCode::Enter => self.toggle_editing(),
-            KeyCode::Backspace => {
-                self.input_arena.value_pop(focused.into())?;
-            }
-            KeyCode::Esc => {
+            KeyCode::Enter => {
+                let annotation = self.input_arena.get_content(&InputId::Annotation)?.clone();
+                if let Err(err) = self.model.set_annotation(rowid, &annotation) {
+                    tracing::error!("{err:#}");
+                    self.state
+                        .push_toast(&format!("{err:#}"), TOAST_DURATION, ERROR_TOAST_STYLE);
+                    self.state.current_screen = CurrentScreen::Main(MainInput::None);
+                    return Ok(());
+                }
+                // keep the loaded items in sync rather than querying them all again
+                if let Some(item) = self.state.items.iter_mut().find(|i| i.rowid == rowid) {
+                    item.annotation = Some(annotation);
+                }
+                // lets get_data pick up the annotated header
+                self.state.last_queried_index = None;
                 self.state.current_screen = CurrentScreen::Main(MainInput::None);
             }
-            KeyCode::Tab => {
-                self.toggle_editing();
+            KeyCode::Esc => self.state.current_screen = CurrentScreen::Main(MainInput::None),
+            KeyCode::Backspace => self.input_arena.value_pop(&InputId::Annotation)?,
+            KeyCode::Char(value) => self.input_arena.value_push(&InputId::Annotation, value)?,
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn handle_key_event_statistics_screen(&mut self, key_event: KeyEvent) {
+        let buckets = &mut self.state.sparkline_buckets;
+        match key_event.code {
+            KeyCode::Char('i' | 'q') | KeyCode::Esc => {
+                self.state.current_screen = CurrentScreen::Main(MainInput::None)
+            }
+            KeyCode::Char('+') => *buckets = (*buckets + 1).min(*SPARKLINE_BUCKETS.end()),
+            KeyCode::Char('-') => *buckets = (*buckets - 1).max(*SPARKLINE_BUCKETS.start()),
+            _ => {}
+        }
+    }
+
+    fn open_size_filter(&mut self) -> Result<()> {
+        self.input_arena.reset_field(&InputId::SizeFilter)?;
+        if let Some((min, max)) = self.state.size_filter {
+            for value in format!("{min}-{max}").chars() {
+                self.input_arena.value_push(&InputId::SizeFilter, value)?;
             }
-            KeyCode::Char(value) => {
-                self.input_arena.value_push(focused.into(), value)?;
+        }
+        self.state.edit_error = None;
+        self.state.current_screen = CurrentScreen::SizeFilter;
+        Ok(())
+    }
+
+    fn handle_key_event_size_filter_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+        match key_event.code {
+            KeyCode::Enter => {
+                match parse_size_range(self.input_arena.get_content(&InputId::SizeFilter)?) {
+                    Ok(size_filter) => {
+                        self.state.size_filter = size_filter;
+                        self.state.current_screen = CurrentScreen::Main(MainInput::None);
+                    }
+                    Err(err) => self.state.edit_error = Some(format!("{err:#}")),
+                }
             }
+            KeyCode::Esc => self.state.current_screen = CurrentScreen::Main(MainInput::None),
+            KeyCode::Backspace => self.input_arena.value_pop(&InputId::SizeFilter)?,
+            KeyCode::Char(value) => self.input_arena.value_push(&InputId::SizeFilter, value)?,
             _ => {}
         };
 
         Ok(())
     }
 
-    // THE update function
-    pub fn handle_events(&mut self) -> Result<()> {
-        match event::read()? {
-            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                match &self.state.current_screen {
-                    CurrentScreen::Main(focused) => {
-                        self.handle_key_event_main_screen(key_event, &focused.clone())?
-                    }
-                    CurrentScreen::Exiting => self.handle_key_event_exit_screen(key_event)?,
-                    CurrentScreen::Editing(focused) => {
-                        self.handle_key_event_edit_screen(key_event, &focused.clone())?
+    fn open_diff(&mut self) -> Result<()> {
+        let [left, right] = self.state.multi_selection[..] else {
+            self.state.push_toast(
+                "Select exactly two protos to diff",
+                TOAST_DURATION,
+                ERROR_TOAST_STYLE,
+            );
+            return Ok(());
+        };
+        self.state.data_diff = Model::data_diff(
+            &String::from_utf8_lossy(&self.model.query_data_bytes(&left)?),
+            &String::from_utf8_lossy(&self.model.query_data_bytes(&right)?),
+        );
+        self.state.diff_scroll = 0;
+        self.state.current_screen = CurrentScreen::Diff { left, right };
+        Ok(())
+    }
+
+    fn handle_key_event_diff_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+        match key_event.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.state.current_screen = CurrentScreen::Main(MainInput::None)
+            }
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.state.diff_scroll = self.state.diff_scroll.saturating_add(1)
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.state.diff_scroll = self.state.diff_scroll.saturating_sub(1)
+            }
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn open_schema_view(&mut self) -> Result<()> {
+        let mut schema = Vec::new();
+        for table in self.model.list_tables()? {
+            let columns = self.model.list_columns(&table)?;
+            schema.push((table, columns));
+        }
+        self.state.schema = schema;
+        self.state.schema_scroll = 0;
+        self.state.current_screen = CurrentScreen::SchemaView;
+        Ok(())
+    }
+
+    fn handle_key_event_schema_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+        match key_event.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.state.current_screen = CurrentScreen::Main(MainInput::None)
+            }
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.state.schema_scroll = self.state.schema_scroll.saturating_add(1)
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.state.schema_scroll = self.state.schema_scroll.saturating_sub(1)
+            }
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn handle_key_event_sql_query_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+        let query = self.input_arena.get_lines_mut(&InputId::SqlQuery)?;
+        match key_event.code {
+            KeyCode::Char('n') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                query.insert_line()
+            }
+            KeyCode::Enter => {
+                let sql = query.text();
+                match self.model.execute_custom_query(&sql) {
+                    Ok(rows) => {
+                        let columns = self.model.custom_query_columns(&sql)?;
+                        self.state.sql_result = Some((columns, rows));
+                        self.state.current_screen = CurrentScreen::Main(MainInput::None);
                     }
+                    Err(err) => self.state.sql_error = Some(format!("{err:#}")),
                 }
             }
+            KeyCode::Esc => self.state.current_screen = CurrentScreen::Main(MainInput::None),
+            KeyCode::Up => query.previous_line(),
+            KeyCode::Down => query.next_line(),
+            KeyCode::Backspace => query.pop(),
+            KeyCode::Char(value) => query.push(value),
             _ => {}
         };
 
-        self.state.filter(
-            self.input_arena
-                .get_content(&MainInput::Filter.try_into()?)?,
-        )?;
-        self.state.get_data(&self.model)?;
-
         Ok(())
     }
+
+    fn handle_key_event_view_picker_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+        match key_event.code {
+            KeyCode::Down => {
+                self.state.view_selected =
+                    (self.state.view_selected + 1) % (self.state.views.len() + 1)
+            }
+            KeyCode::Up => {
+                self.state.view_selected = self
+                    .state
+                    .view_selected
+                    .checked_sub(1)
+                    .unwrap_or(self.state.views.len())
+            }
+            KeyCode::Enter => {
+                let view = self
+                    .state
+                    .view_selected
+                    .checked_sub(1)
+                    .and_then(|i| self.state.views.get(i))
+                    .cloned();
+                let parameters = match view.as_deref().map(|v| self.model.view_parameters(v)) {
+                    Some(Ok(parameters)) => parameters,
+                    Some(Err(err)) => {
+                        tracing::error!("{err:#}");
+                        self.state.push_toast(
+                            &format!("{err:#}"),
+                            TOAST_DURATION,
+                            ERROR_TOAST_STYLE,
+                        );
+                        return Ok(());
+                    }
+                    None => Vec::new(),
+                };
+                match view {
+                    // the inputs keep their values, so the previous ones are suggested
+                    Some(view) if !parameters.is_empty() => {
+                        let fields = parameters
+                            .iter()
+                            .map(|name| (name.clone(), self.input_arena.register(name).clone()))
+                            .collect();
+                        self.state.current_screen = CurrentScreen::ViewParameters {
+                            view,
+                            fields,
+                            focused: 0,
+                        };
+                    }
+                    view => self.open_view(view, Vec::new()),
+                }
+            }
+            KeyCode::Esc => self.state.current_screen = CurrentScreen::Main(MainInput::None),
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn handle_key_event_view_parameters_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+        let CurrentScreen::ViewParameters {
+            view,
+            fields,
+            focused,
+        } = &mut self.state.current_screen
+        else {
+            return Ok(());
+        };
+        match key_event.code {
+            KeyCode::Enter => {
+                let view = view.clone();
+                let parameters = fields
+                    .iter()
+                    .map(|(name, id)| Ok((name.clone(), self.input_arena.get_content(id)?.clone())))
+                    .collect::<Result<_>>()?;
+                self.open_view(Some(view), parameters);
+            }
+            KeyCode::Esc => self.state.current_screen = CurrentScreen::Main(MainInput::None),
+            KeyCode::Tab | KeyCode::Down => *focused = (*focused + 1) % fields.len(),
+            KeyCode::BackTab | KeyCode::Up => {
+                *focused = (*focused + fields.len() - 1) % fields.len()
+            }
+            KeyCode::Backspace => self.input_arena.value_pop(&fields[*focused].1)?,
+            KeyCode::Char(value) => self.input_arena.value_push(&fields[*focused].1, value)?,
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    // `None` goes back to the full proto list
+    fn open_view(&mut self, view: Option<String>, parameters: Vec<(String, String)>) {
+        let previous = (
+            std::mem::replace(&mut self.state.active_view, view),
+            std::mem::replace(&mut self.state.view_parameters, parameters),
+        );
+        self.state.current_screen = CurrentScreen::Main(MainInput::None);
+        // a broken view leaves the list as it was
+        if let Err(err) = self.refresh_items() {
+            (self.state.active_view, self.state.view_parameters) = previous;
+            tracing::error!("{err:#}");
+            self.state
+                .push_toast(&format!("{err:#}"), TOAST_DURATION, ERROR_TOAST_STYLE);
+        }
+        self.state.update_state(0);
+    }
+
+    fn open_session_picker(&mut self) -> Result<()> {
+        if self.state.session_counts.is_empty() {
+            self.state.session_counts = self.model.count_protos_by_session()?;
+        }
+        let sessions = self.state.picker_sessions();
+        self.state.session_selected = self
+            .state
+            .sql_filter
+            .session_eq
+            .and_then(|current| sessions.iter().position(|(id, _)| *id == current))
+            .map_or(0, |i| i + 1);
+        self.state.current_screen = CurrentScreen::SessionPicker;
+        Ok(())
+    }
+
+    fn handle_key_event_session_picker_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+        let lines = self.state.picker_sessions().len() + 1;
+        match key_event.code {
+            KeyCode::Down => {
+                self.state.session_selected = (self.state.session_selected + 1) % lines
+            }
+            KeyCode::Up => {
+                self.state.session_selected = self
+                    .state
+                    .session_selected
+                    .checked_sub(1)
+                    .unwrap_or(lines - 1)
+            }
+            KeyCode::Enter => {
+                let session = self
+                    .state
+                    .session_selected
+                    .checked_sub(1)
+                    .and_then(|i| self.state.picker_sessions().get(i).map(|(id, _)| *id));
+                self.state.sql_filter.session_eq = session;
+                self.state.current_screen = CurrentScreen::Main(MainInput::None);
+                self.refresh_items()?;
+                self.state.update_state(0);
+            }
+            KeyCode::Esc => self.state.current_screen = CurrentScreen::Main(MainInput::None),
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn show_next_conflict(&mut self) {
+        self.state.current_screen = match self.state.merge_conflicts.first() {
+            Some(conflict) => CurrentScreen::MergeConflict {
+                key: conflict.key.clone(),
+                ours: conflict.ours.clone(),
+                theirs: conflict.theirs.to_string().trim().to_owned(),
+            },
+            None => CurrentScreen::Main(MainInput::None),
+        };
+    }
+
+    fn handle_key_event_merge_conflict_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+        match key_event.code {
+            KeyCode::Char('o') => {
+                self.state.merge_conflicts.remove(0);
+            }
+            KeyCode::Char('t') => {
+                let conflict = self.state.merge_conflicts.remove(0);
+                self.model.set_layer_key(&conflict.key, conflict.theirs)?;
+                self.pending_save = Some(Instant::now());
+            }
+            // keep ours for every remaining conflict
+            KeyCode::Esc => self.state.merge_conflicts.clear(),
+            _ => return Ok(()),
+        };
+
+        self.show_next_conflict();
+        Ok(())
+    }
+
+    fn handle_key_event_confirm_save_screen(&mut self, key_event: KeyEvent) -> Result<()> {
+        match key_event.code {
+            KeyCode::Char('y') => {
+                // saved by on_tick once edits settle down
+                self.pending_save = Some(Instant::now());
+                self.state.current_screen = CurrentScreen::Main(MainInput::None);
+            }
+            KeyCode::Char('n') | KeyCode::Esc => {
+                // drop the pending change by going back to the saved layer
+                self.model.reload_layer()?;
+                self.state.current_screen = CurrentScreen::Main(MainInput::None);
+            }
+            KeyCode::Down => self.state.diff_scroll = self.state.diff_scroll.saturating_add(1),
+            KeyCode::Up => self.state.diff_scroll = self.state.diff_scroll.saturating_sub(1),
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn open_add_proto(&mut self) -> Result<()> {
+        if !self.model.is_writable() {
+            self.state
+                .push_toast(READ_ONLY_MESSAGE, TOAST_DURATION, ERROR_TOAST_STYLE);
+            return Ok(());
+        }
+        self.input_arena.reset_all(&NEW_PROTO_FIELDS)?;
+        self.state.edit_error = None;
+        self.state.current_screen = CurrentScreen::AddProto(InputId::NewSession);
+        Ok(())
+    }
+
+    fn complete_add_proto(&mut self) -> Result<()> {
+        let session = self.input_arena.get_content(&InputId::NewSession)?;
+        let session = if session.is_empty() {
+            None
+        } else {
+            match session.parse() {
+                Ok(session) => Some(session),
+                Err(_) => {
+                    self.state.edit_error = Some(format!("`{session}` is not a session id"));
+                    return Ok(());
+                }
+            }
+        };
+        let name = self.input_arena.get_content(&InputId::NewName)?;
+        if name.is_empty() {
+            self.state.edit_error = Some(String::from("The name cannot be empty"));
+            return Ok(());
+        }
+
+        let added = self.model.add_proto(
+            session,
+            name,
+            self.input_arena.get_content(&InputId::NewTimestamp)?,
+            self.input_arena.get_content(&InputId::NewData)?,
+        );
+        let rowid = match added {
+            Ok(rowid) => rowid,
+            Err(err) => {
+                self.state.edit_error = Some(format!("{err:#}"));
+                return Ok(());
+            }
+        };
+        self.state.current_screen = CurrentScreen::Main(MainInput::None);
+        self.select_added_proto(rowid)
+    }
+
+    // reloads the list and highlights the proto just inserted at `rowid`
+    fn select_added_proto(&mut self, rowid: usize) -> Result<()> {
+        self.refresh_items()?;
+        // the filter may hide it
+        let items = &self.state.items;
+        if let Some(position) = self
+            .state
+            .filtered_indexes
+            .iter()
+            .position(|i| items[*i].rowid == rowid)
+        {
+            self.state.update_state(position);
+            self.state.keep_selection_visible();
+        }
+        self.state.added_highlight = Some((rowid, Instant::now()));
+        Ok(())
+    }
+
+    fn handle_key_event_add_proto_screen(
+        &mut self,
+        key_event: KeyEvent,
+        focused: &InputId,
+    ) -> Result<()> {
+        let index = NEW_PROTO_FIELDS
+            .iter()
+            .position(|f| f == focused)
+            .unwrap_or(0);
+        match key_event.code {
+            KeyCode::Enter => self.complete_add_proto()?,
+            KeyCode::Esc => self.state.current_screen = CurrentScreen::Main(MainInput::None),
+            KeyCode::Tab | KeyCode::Down => {
+                let next = NEW_PROTO_FIELDS[(index + 1) % NEW_PROTO_FIELDS.len()].clone();
+                self.state.current_screen = CurrentScreen::AddProto(next);
+            }
+            KeyCode::BackTab | KeyCode::Up => {
+                let previous = (index + NEW_PROTO_FIELDS.len() - 1) % NEW_PROTO_FIELDS.len();
+                self.state.current_screen =
+                    CurrentScreen::AddProto(NEW_PROTO_FIELDS[previous].clone());
+            }
+            KeyCode::Backspace => self.input_arena.value_pop(focused)?,
+            KeyCode::Char(value) => self.push_limited(focused, value)?,
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn duplicate_selected_proto(&mut self) -> Result<()> {
+        let Some(rowid) = self.state.selected_rowid() else {
+            return Ok(());
+        };
+        if !self.model.is_writable() {
+            self.state
+                .push_toast(READ_ONLY_MESSAGE, TOAST_DURATION, ERROR_TOAST_STYLE);
+            return Ok(());
+        }
+        match self.model.duplicate_proto(rowid) {
+            Ok(copy) => self.select_added_proto(copy),
+            Err(err) => {
+                tracing::error!("{err:#}");
+                self.state
+                    .push_toast(&format!("{err:#}"), TOAST_DURATION, ERROR_TOAST_STYLE);
+                Ok(())
+            }
+        }
+    }
+
+    fn open_delete_proto(&mut self) {
+        let Some(header) = self.state.selected_header() else {
+            return;
+        };
+        if !self.model.is_writable() {
+            self.state
+                .push_toast(READ_ONLY_MESSAGE, TOAST_DURATION, ERROR_TOAST_STYLE);
+            return;
+        }
+        self.state.current_screen = CurrentScreen::ConfirmDeleteProto {
+            rowid: header.rowid,
+            name: header.name.clone(),
+        };
+    }
+
+    fn handle_key_event_confirm_delete_proto_screen(
+        &mut self,
+        key_event: KeyEvent,
+        rowid: usize,
+    ) -> Result<()> {
+        match key_event.code {
+            KeyCode::Char('y') => {
+                self.state.current_screen = CurrentScreen::Main(MainInput::None);
+                if let Err(err) = self.model.delete_proto(rowid) {
+                    tracing::error!("{err:#}");
+                    self.state
+                        .push_toast(&format!("{err:#}"), TOAST_DURATION, ERROR_TOAST_STYLE);
+                    return Ok(());
+                }
+                self.state.multi_selection.retain(|r| *r != rowid);
+                self.refresh_items()?;
+            }
+            KeyCode::Char('n') | KeyCode::Esc => {
+                self.state.current_screen = CurrentScreen::Main(MainInput::None);
+            }
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn handle_key_event_confirm_delete_screen(
+        &mut self,
+        key_event: KeyEvent,
+        path: &[String],
+    ) -> Result<()> {
+        match key_event.code {
+            KeyCode::Char('y') => {
+                let path: Vec<&str> = path.iter().map(String::as_str).collect();
+                self.model.delete_layer_key(&path)?;
+                // saved by on_tick like any other layer change
+                self.pending_save = Some(Instant::now());
+                // bookmarks and aliases live in the layer too
+                self.state.bookmarks = self.model.query_bookmarks();
+                self.state.invalidate_filter();
+                self.state.column_aliases = self.model.column_aliases();
+                self.state.load_layer_entries(&self.model)?;
+                let last = self.state.layer_entries.len().checked_sub(1);
+                let selected = self.state.layer_state.selected();
+                self.state.layer_state.select(selected.min(last));
+                self.state.current_screen = CurrentScreen::Main(MainInput::Layer);
+            }
+            KeyCode::Char('n') | KeyCode::Esc => {
+                self.state.current_screen = CurrentScreen::Main(MainInput::Layer);
+            }
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn handle_key_event_edit_screen(
+        &mut self,
+        key_event: KeyEvent,
+        focused: &EditingInput,
+    ) -> Result<()> {
+        let multiline = self.input_arena.get(focused.into())?.is_multiline();
+        let control = key_event.modifiers.contains(KeyModifiers::CONTROL);
+        match key_event.code {
+            // most terminals cannot tell Ctrl+Enter from Enter, hence Ctrl+S
+            KeyCode::Enter | KeyCode::Char('s') if multiline && control => {
+                self.complete_editing()?
+            }
+            KeyCode::Enter if multiline => self.push_limited(focused.into(), '\n')?,
+            KeyCode::Enter => match focused {
+                EditingInput::Key => self.toggle_editing(),
+                EditingInput::Value => self.complete_editing()?,
+            },
+            KeyCode::Backspace => {
+                self.input_arena.value_pop(focused.into())?;
+            }
+            KeyCode::Esc => {
+                self.input_arena.reset_all(&EDIT_FIELDS)?;
+                self.state.current_screen = CurrentScreen::Main(MainInput::None);
+            }
+            KeyCode::Tab => match focused {
+                EditingInput::Key => self
+                    .input_arena
+                    .get_mut(focused.into())?
+                    .autocomplete(&self.state.layer_key_candidates),
+                EditingInput::Value => self.toggle_editing(),
+            },
+            KeyCode::Char('t') if control => {
+                self.state.value_type = self.state.value_type.next();
+            }
+            // Ctrl+M being Enter for the terminal, multi-line mode is toggled with Ctrl+L
+            KeyCode::Char('l') if control => {
+                if let EditingInput::Value = focused {
+                    self.input_arena.get_mut(focused.into())?.toggle_multiline();
+                }
+            }
+            KeyCode::Char(value) => {
+                self.push_limited(focused.into(), value)?;
+            }
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn handle_mouse_event(&mut self, mouse_event: MouseEvent) -> Result<()> {
+        let area = self.state.breadcrumb_area;
+        if let MouseEventKind::Down(MouseButton::Left) = mouse_event.kind {
+            if mouse_event.row == area.y && (area.x..area.right()).contains(&mouse_event.column) {
+                if let Some(depth) = self.state.breadcrumb_depth_at(mouse_event.column) {
+                    self.state.navigate_layer_to(depth, &self.model)?;
+                }
+                return Ok(());
+            }
+        }
+
+        if let CurrentScreen::Main(MainInput::None) = &self.state.current_screen {
+            match mouse_event.kind {
+                MouseEventKind::ScrollDown => self.state.next_row()?,
+                MouseEventKind::ScrollUp => self.state.previous_row()?,
+                _ => {}
+            };
+            // other rows are now under the mouse
+            self.state.hover_tooltip = None;
+        }
+
+        Ok(())
+    }
+
+    fn handle_mouse_motion(&mut self, column: u16, row: u16) {
+        self.state.hover_tooltip = match &self.state.current_screen {
+            CurrentScreen::Main(_) => self.state.tooltip_at(column, row),
+            _ => None,
+        };
+    }
+
+    // layouts are computed again on the next draw, only what was kept from the last one is reset
+    fn handle_resize_event(&mut self, _cols: u16, rows: u16) {
+        self.state.breadcrumb_area = Rect::default();
+        self.state.table_area = Rect::default();
+        self.state.hover_tooltip = None;
+        self.state.visible_rows = self.state.layout.visible_rows(rows);
+        self.state.keep_selection_visible();
+    }
+
+    fn refresh_items(&mut self) -> Result<()> {
+        self.state.refresh(
+            &mut self.model,
+            self.input_arena
+                .get_content(&MainInput::Filter.try_into()?)?,
+        )
+    }
+
+    // views are read right away, they are usually small and may use any table
+    fn start_refresh(&mut self) -> Result<()> {
+        if self.state.active_view.is_some() || !self.model.is_connected() {
+            return self.refresh_items();
+        }
+        match self
+            .model
+            .query_protos_in_background(self.state.sql_filter.clone(), self.state.sql_sort())
+        {
+            Some(receiver) => {
+                self.refreshing = Some((receiver, self.state.sql_sort()));
+                self.state.loading = true;
+                Ok(())
+            }
+            None => self.refresh_items(),
+        }
+    }
+
+    fn poll_refresh(&mut self) -> Result<()> {
+        let Some((receiver, sorted_by)) = &self.refreshing else {
+            return Ok(());
+        };
+        let items = match receiver.try_recv() {
+            Ok(items) => items,
+            Err(TryRecvError::Empty) => {
+                self.state.spinner_frame = (self.state.spinner_frame + 1) % 8;
+                return Ok(());
+            }
+            Err(TryRecvError::Disconnected) => Err(Error::msg("The refresh was interrupted")),
+        };
+        let sorted_by = *sorted_by;
+        self.refreshing = None;
+        self.state.loading = false;
+        self.state.spinner_frame = 0;
+
+        match items {
+            // ticks without events do not filter the items again
+            Ok(items) => self.state.set_items(
+                items,
+                sorted_by,
+                self.input_arena
+                    .get_content(&MainInput::Filter.try_into()?)?,
+            )?,
+            Err(err) => {
+                tracing::error!("{err:#}");
+                self.state
+                    .push_toast(&format!("{err:#}"), TOAST_DURATION, ERROR_TOAST_STYLE)
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_pending_save(&mut self) -> Result<()> {
+        if self.pending_save.take().is_some() {
+            self.model.save_layer()?;
+            self.last_save = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    pub fn on_tick(&mut self) -> Result<()> {
+        self.poll_refresh()?;
+        self.state.expire_toasts();
+        let tick = Duration::from_millis(self.tick_rate);
+        self.state
+            .input_overflow_flash
+            .retain(|_, typed| typed.elapsed() < tick);
+        if self
+            .state
+            .added_highlight
+            .is_some_and(|(_, added)| added.elapsed() > ADDED_HIGHLIGHT_DURATION)
+        {
+            self.state.added_highlight = None;
+        }
+
+        if self
+            .pending_save
+            .is_some_and(|modified| modified.elapsed() > AUTOSAVE_DELAY)
+        {
+            self.flush_pending_save()?;
+        }
+
+        self.state.save_status = if self.pending_save.is_some() {
+            SaveStatus::Dirty
+        } else if self
+            .last_save
+            .is_some_and(|saved| saved.elapsed() < SAVED_INDICATOR_DURATION)
+        {
+            SaveStatus::Saved
+        } else {
+            SaveStatus::Clean
+        };
+
+        Ok(())
+    }
+
+    // THE update function
+    pub fn handle_events(&mut self, next_frame: Instant) -> Result<()> {
+        // do not block forever so the main loop can notice termination requests
+        if event::poll(Duration::from_millis(self.tick_rate))? {
+            self.handle_event(event::read()?)?;
+            // batch the events arriving until the next frame is due, then drain
+            // the pending ones, so a burst of input costs a single draw
+            while !self.exit && event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+                self.handle_event(event::read()?)?;
+            }
+        }
+        // also after events, so that holding a key does not delay the timers
+        self.on_tick()
+    }
+
+    // dispatches the event to the current screen, then updates the derived state
+    pub fn handle_event(&mut self, event: Event) -> Result<()> {
+        match event {
+            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                let screen = self.state.current_screen.to_string();
+                tracing::debug!(key = ?key_event.code, modifiers = ?key_event.modifiers, %screen, "key pressed");
+                let handled = match &self.state.current_screen {
+                    CurrentScreen::Main(focused) => {
+                        self.handle_key_event_main_screen(key_event, &focused.clone())
+                    }
+                    CurrentScreen::ConfirmSave { .. } => {
+                        self.handle_key_event_confirm_save_screen(key_event)
+                    }
+                    CurrentScreen::Annotating { rowid } => {
+                        self.handle_key_event_annotation_screen(key_event, *rowid)
+                    }
+                    CurrentScreen::Diff { .. } => self.handle_key_event_diff_screen(key_event),
+                    CurrentScreen::SchemaView => self.handle_key_event_schema_screen(key_event),
+                    CurrentScreen::SqlQuery => self.handle_key_event_sql_query_screen(key_event),
+                    CurrentScreen::ViewPicker => {
+                        self.handle_key_event_view_picker_screen(key_event)
+                    }
+                    CurrentScreen::ViewParameters { .. } => {
+                        self.handle_key_event_view_parameters_screen(key_event)
+                    }
+                    CurrentScreen::SessionPicker => {
+                        self.handle_key_event_session_picker_screen(key_event)
+                    }
+                    CurrentScreen::SizeFilter => {
+                        self.handle_key_event_size_filter_screen(key_event)
+                    }
+                    CurrentScreen::Statistics => {
+                        self.handle_key_event_statistics_screen(key_event);
+                        Ok(())
+                    }
+                    CurrentScreen::MergeConflict { .. } => {
+                        self.handle_key_event_merge_conflict_screen(key_event)
+                    }
+                    CurrentScreen::ConfirmDelete { path } => {
+                        self.handle_key_event_confirm_delete_screen(key_event, &path.clone())
+                    }
+                    CurrentScreen::ConfirmDeleteProto { rowid, .. } => {
+                        self.handle_key_event_confirm_delete_proto_screen(key_event, *rowid)
+                    }
+                    CurrentScreen::AddProto(focused) => {
+                        self.handle_key_event_add_proto_screen(key_event, &focused.clone())
+                    }
+                    CurrentScreen::Exiting { dirty } => {
+                        self.handle_key_event_exit_screen(key_event, *dirty)
+                    }
+                    CurrentScreen::Editing(focused) => {
+                        self.handle_key_event_edit_screen(key_event, &focused.clone())
+                    }
+                };
+                handled
+                    .with_context(|| format!("Could not handle a key in the `{screen}` screen"))?;
+                let next = self.state.current_screen.to_string();
+                if next != screen {
+                    tracing::info!(from = %screen, to = %next, "screen changed");
+                }
+            }
+            // motion arrives by the dozen and only moves the tooltip, the state
+            // derived below cannot have changed
+            Event::Mouse(mouse_event)
+                if self.mouse && mouse_event.kind == MouseEventKind::Moved =>
+            {
+                self.handle_mouse_motion(mouse_event.column, mouse_event.row);
+                return Ok(());
+            }
+            Event::Mouse(mouse_event) if self.mouse => self.handle_mouse_event(mouse_event)?,
+            Event::Resize(cols, rows) => self.handle_resize_event(cols, rows),
+            _ => {}
+        };
+
+        match self.state.current_screen.focused_input() {
+            Some(focused) => self.input_arena.set_active(&focused)?,
+            None => self.input_arena.clear_active(),
+        }
+
+        self.state.filter(
+            self.input_arena
+                .get_content(&MainInput::Filter.try_into()?)?,
+        )?;
+        self.state.get_data(&mut self.model)?;
+        self.state.cache_stats = self.model.cache_stats();
+        self.state.load_layer_entries(&self.model)?;
+        if self.state.layer_json.is_some() {
+            self.state.layer_json = Some(self.model.layer_as_json()?);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        // guarantee the terminal is usable again whatever the exit path
+        let _ = tui::restore();
+    }
+}
+
+// the rest of a `!token` filter token, a lone `!` being matched literally
+fn negated_token(token: &str) -> Option<&str> {
+    token.strip_prefix('!').filter(|rest| !rest.is_empty())
+}
+
+pub fn filter_has_negation(filter: &str) -> bool {
+    filter
+        .split_whitespace()
+        .any(|token| negated_token(token).is_some())
+}
+
+// finds the characters of the proto names matched by the filter, built once per
+// table rather than once per name
+pub enum FilterHighlighter {
+    Exact(Vec<String>), // tokens to find, the negated ones matching nothing
+    Regex(Regex),
+    Fuzzy(Box<SkimMatcherV2>, String),
+}
+
+impl FilterHighlighter {
+    // nothing to highlight for an empty filter, a tag filter or an invalid regex
+    pub fn new(filter: &str, mode: FilterMode) -> Option<Self> {
+        if filter.is_empty() || filter.starts_with('#') {
+            return None;
+        }
+        match mode {
+            FilterMode::Exact => Some(FilterHighlighter::Exact(
+                filter
+                    .split_whitespace()
+                    .filter(|token| negated_token(token).is_none())
+                    .map(str::to_owned)
+                    .collect(),
+            )),
+            FilterMode::Regex => Regex::new(filter).ok().map(FilterHighlighter::Regex),
+            FilterMode::Fuzzy => Some(FilterHighlighter::Fuzzy(Box::default(), filter.to_owned())),
+        }
+    }
+
+    // indices of the matched characters, in order
+    pub fn positions(&self, name: &str) -> Vec<usize> {
+        let ranges: Vec<std::ops::Range<usize>> = match self {
+            FilterHighlighter::Exact(tokens) => tokens
+                .iter()
+                .flat_map(|token| {
+                    name.match_indices(token.as_str())
+                        .map(|(start, found)| start..start + found.len())
+                })
+                .collect(),
+            // the first capture group, or the whole match without any
+            FilterHighlighter::Regex(regex) => regex
+                .captures(name)
+                .and_then(|captures| captures.get(1).or(captures.get(0)))
+                .map(|found| found.range())
+                .into_iter()
+                .collect(),
+            FilterHighlighter::Fuzzy(matcher, pattern) => {
+                return matcher
+                    .fuzzy_indices(name, pattern)
+                    .map(|(_, indices)| indices)
+                    .unwrap_or_default()
+            }
+        };
+        name.char_indices()
+            .enumerate()
+            .filter(|(_, (byte, _))| ranges.iter().any(|range| range.contains(byte)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+// seconds of a `YYYY-MM-DD HH:MM:SS` timestamp, fractional seconds and a `T`
+// separator being accepted
+fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(timestamp, format).ok())
+        .map(|time| time.and_utc().timestamp())
+}
+
+// `min-max` in bytes, either bound being optional, an empty range clearing the filter
+fn parse_size_range(range: &str) -> Result<Option<(usize, usize)>> {
+    let range = range.trim();
+    if range.is_empty() {
+        return Ok(None);
+    }
+    let (min, max) = range
+        .split_once('-')
+        .context("Expected a `min-max` range in bytes")?;
+    let min = match min.trim() {
+        "" => 0,
+        min => min
+            .parse()
+            .with_context(|| format!("Invalid minimum `{min}`"))?,
+    };
+    let max = match max.trim() {
+        "" => usize::MAX,
+        max => max
+            .parse()
+            .with_context(|| format!("Invalid maximum `{max}`"))?,
+    };
+    if min > max {
+        return Err(Error::msg("The minimum is greater than the maximum"));
+    }
+    Ok(Some((min, max)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+    use toml_edit::DocumentMut;
+
+    use super::*;
+    use crate::layer::LayerAccess;
+
+    fn fixture_state() -> AppState {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tcp_proto_messages (session INT, proto TEXT, timestamp TEXT, data TEXT);
+             INSERT INTO tcp_proto_messages VALUES (1, 'tcp_login', '2024-01-01 00:00:00', '{}');
+             INSERT INTO tcp_proto_messages VALUES (1, 'udp_ping', '2024-01-01 00:00:01', '{}');
+             INSERT INTO tcp_proto_messages VALUES (2, 'tcp_move', '2024-01-01 00:00:02', '{}');",
+        )
+        .unwrap();
+        let mut model =
+            Model::from_connection(conn, DocumentMut::new().into(), std::path::PathBuf::new());
+
+        let mut state = AppState::new(&mut model).unwrap();
+        state.filter("").unwrap();
+        state
+    }
+
+    #[test]
+    fn new_state_accepts_an_empty_table() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tcp_proto_messages (session INT, proto TEXT, timestamp TEXT, data TEXT);",
+        )
+        .unwrap();
+        let mut model =
+            Model::from_connection(conn, DocumentMut::new().into(), std::path::PathBuf::new());
+
+        let mut state = AppState::new(&mut model).unwrap();
+        state.filter("").unwrap();
+
+        assert!(state.items.is_empty());
+        assert_eq!(state.state.selected(), None);
+        assert!(state.get_data(&mut model).is_ok());
+    }
+
+    fn fixture_app() -> App {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tcp_proto_messages (session INT, proto TEXT, timestamp TEXT, data TEXT);
+             INSERT INTO tcp_proto_messages VALUES (1, 'tcp_login', '2024-01-01 00:00:00', '{}');
+             INSERT INTO tcp_proto_messages VALUES (1, 'udp_ping', '2024-01-01 00:00:01', '{}');
+             INSERT INTO tcp_proto_messages VALUES (2, 'tcp_move', '2024-01-01 00:00:02', '{}');",
+        )
+        .unwrap();
+        let mut model = Model::from_connection(conn, DocumentMut::new().into(), PathBuf::new());
+        let state = AppState::new(&mut model).unwrap();
+
+        App {
+            model,
+            state,
+            input_arena: InputArena::new(),
+            ui_state_path: None,
+            tick_rate: 100,
+            fps: 30,
+            mouse: false,
+            pending_save: None,
+            last_save: None,
+            exit: false,
+            refreshing: None,
+        }
+    }
+
+    fn press(app: &mut App, keys: &[KeyCode]) {
+        for code in keys {
+            app.handle_event(Event::Key(KeyEvent::new(*code, KeyModifiers::NONE)))
+                .unwrap();
+        }
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        let keys: Vec<KeyCode> = text.chars().map(KeyCode::Char).collect();
+        press(app, &keys);
+    }
+
+    #[test]
+    fn builder_applies_the_theme_and_keybindings() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("capture.db");
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE tcp_proto_messages (session INT, proto TEXT, timestamp TEXT, data TEXT);",
+            )
+            .unwrap();
+        let mut app = AppBuilder::default()
+            .db_path(db_path)
+            .layer_path(dir.path().join("layer.toml"))
+            .create_layer(true)
+            .theme(Theme {
+                accent: Color::Magenta,
+                ..Theme::default()
+            })
+            .keybindings(KeyBindings {
+                quit: KeyCode::Char('x'),
+                ..KeyBindings::default()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(app.state.theme.accent, Color::Magenta);
+
+        press(&mut app, &[KeyCode::Char('x')]);
+        assert!(matches!(
+            app.state.current_screen,
+            CurrentScreen::Exiting { dirty: false }
+        ));
+    }
+
+    #[test]
+    fn typing_a_filter_narrows_the_list() {
+        let mut app = fixture_app();
+
+        press(&mut app, &[KeyCode::Char('f')]);
+        type_text(&mut app, "tcp");
+
+        assert_eq!(app.state.filtered_indexes, vec![0, 2]);
+    }
+
+    #[test]
+    fn session_picker_keeps_the_chosen_session() {
+        let mut app = fixture_app();
+
+        press(&mut app, &[KeyCode::Char('g')]);
+        assert_eq!(app.state.picker_sessions(), vec![(1, 2), (2, 1)]);
+        press(&mut app, &[KeyCode::Down, KeyCode::Down, KeyCode::Enter]);
+
+        assert_eq!(app.state.sql_filter.session_eq, Some(2));
+        let names: Vec<&str> = app.state.items.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["tcp_move"]);
+        // cleared by the refresh, the counts may have changed meanwhile
+        assert!(app.state.session_counts.is_empty());
+    }
+
+    #[test]
+    fn view_parameters_are_asked_before_opening_the_view() {
+        let mut app = fixture_app();
+        app.model
+            .set_layer_key_at_path(
+                &["views", "by_session"],
+                toml_edit::value(
+                    "SELECT rowid, session, proto, timestamp FROM tcp_proto_messages WHERE session = :session",
+                ),
+            )
+            .unwrap();
+
+        press(
+            &mut app,
+            &[KeyCode::Char('v'), KeyCode::Down, KeyCode::Enter],
+        );
+        assert!(matches!(
+            app.state.current_screen,
+            CurrentScreen::ViewParameters { .. }
+        ));
+        type_text(&mut app, "1");
+        press(&mut app, &[KeyCode::Enter]);
+
+        assert_eq!(app.state.active_view.as_deref(), Some("by_session"));
+        let names: Vec<&str> = app.state.items.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["tcp_login", "udp_ping"]);
+
+        // the value typed last time is kept
+        press(
+            &mut app,
+            &[KeyCode::Char('v'), KeyCode::Down, KeyCode::Enter],
+        );
+        assert_eq!(
+            app.input_arena
+                .get_content(&InputId::Custom(":session".into()))
+                .unwrap(),
+            "1"
+        );
+    }
+
+    #[test]
+    fn ctrl_f_cycles_the_filter_mode() {
+        let mut app = fixture_app();
+        let ctrl_f = Event::Key(KeyEvent::new(KeyCode::Char('f'), KeyModifiers::CONTROL));
+
+        app.handle_event(ctrl_f.clone()).unwrap();
+        assert_eq!(app.state.filter_mode, FilterMode::Fuzzy);
+        assert!(matches!(
+            app.state.current_screen,
+            CurrentScreen::Main(MainInput::None)
+        ));
+
+        press(&mut app, &[KeyCode::Char('f')]);
+        app.handle_event(ctrl_f).unwrap();
+        assert_eq!(app.state.filter_mode, FilterMode::Regex);
+        type_text(&mut app, "g$");
+        assert_eq!(app.state.filtered_indexes, vec![1]);
+    }
+
+    #[test]
+    fn editing_sets_a_layer_key() {
+        let mut app = fixture_app();
+
+        press(&mut app, &[KeyCode::Char('e')]);
+        type_text(&mut app, "alias");
+        press(&mut app, &[KeyCode::Enter]);
+        type_text(&mut app, "test");
+        press(&mut app, &[KeyCode::Enter]);
+
+        assert_eq!(
+            app.model.layer.get(&["alias"]).unwrap().as_str(),
+            Some("test")
+        );
+        assert!(matches!(
+            app.state.current_screen,
+            CurrentScreen::ConfirmSave { .. }
+        ));
+    }
+
+    #[test]
+    fn quitting_a_clean_layer_exits() {
+        let mut app = fixture_app();
+
+        press(&mut app, &[KeyCode::Char('q'), KeyCode::Char('y')]);
+
+        assert!(app.exit);
+    }
+
+    #[test]
+    fn get_data_queries_only_a_newly_selected_row() {
+        let mut app = fixture_app();
+        app.state.filter("").unwrap();
+        app.state.get_data(&mut app.model).unwrap();
+        let queried = app.model.cache_stats();
+
+        app.state.get_data(&mut app.model).unwrap();
+        assert_eq!(app.model.cache_stats(), queried);
+        assert_eq!(app.state.last_queried_index, Some(0));
+
+        app.state.next_row().unwrap();
+        app.state.get_data(&mut app.model).unwrap();
+        assert_ne!(app.model.cache_stats(), queried);
+        assert_eq!(app.state.cached.as_ref().unwrap().0.name, "udp_ping");
+
+        app.state.filter("tcp").unwrap();
+        assert_eq!(app.state.last_queried_index, None);
+        app.state.get_data(&mut app.model).unwrap();
+        assert_eq!(app.state.cached.as_ref().unwrap().0.name, "tcp_move");
+    }
+
+    #[test]
+    fn refresh_lets_sqlite_sort_the_items() {
+        let mut app = fixture_app();
+        app.state.sort_config = SortConfig {
+            key: SortKey::Name,
+            descending: true,
+        };
+
+        app.refresh_items().unwrap();
+        assert_eq!(app.state.items_sorted_by, Some(app.state.sort_config));
+        let names: Vec<&str> = app.state.items.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["udp_ping", "tcp_move", "tcp_login"]);
+        assert_eq!(app.state.filtered_indexes, vec![0, 1, 2]);
+
+        // sorted in Rust until the next refresh
+        app.state.sort_config = SortConfig::default();
+        app.state.filter("").unwrap();
+        assert_eq!(app.state.filtered_indexes, vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn refresh_keeps_the_filter_and_the_selected_proto() {
+        let mut app = fixture_app();
+        app.state.filter("tcp").unwrap();
+        app.state.update_state(1);
+
+        app.model.delete_proto(1).unwrap();
+        app.state.refresh(&mut app.model, "tcp").unwrap();
+
+        assert_eq!(app.state.filtered_indexes, vec![1]);
+        assert_eq!(app.state.selected_rowid(), Some(3));
+
+        // the selected proto is gone, the selection stays in the list
+        app.model.delete_proto(3).unwrap();
+        app.state.refresh(&mut app.model, "").unwrap();
+        assert_eq!(app.state.selected_index, 0);
+        assert_eq!(app.state.selected_rowid(), Some(2));
+    }
+
+    #[test]
+    fn empty_filter_keeps_all_items() {
+        let state = fixture_state();
+
+        assert_eq!(state.filtered_indexes, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn filter_excludes_negated_tokens() {
+        let mut state = fixture_state();
+        state.items = ["tcp_v1", "tcp_v2", "udp_v1"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Header::from(i + 1, None, name.to_string(), String::new()))
+            .collect();
+
+        state.filter("tcp !v1").unwrap();
+
+        let names: Vec<&str> = state
+            .get_filtered_data()
+            .unwrap()
+            .iter()
+            .map(|h| h.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["tcp_v2"]);
+        assert!(filter_has_negation("tcp !v1"));
+        assert!(!filter_has_negation("tcp !"));
+    }
+
+    #[test]
+    fn filter_keeps_matching_items() {
+        let mut state = fixture_state();
+
+        state.filter("tcp").unwrap();
+
+        assert_eq!(state.filtered_indexes, vec![0, 2]);
+        let names: Vec<&str> = state
+            .get_filtered_data()
+            .unwrap()
+            .iter()
+            .map(|h| h.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["tcp_login", "tcp_move"]);
+    }
+
+    #[test]
+    fn regex_filter_keeps_matching_items() {
+        let mut state = fixture_state();
+        state.filter_mode = FilterMode::Regex;
+
+        state.filter("^tcp_(login|ping)$").unwrap();
+        assert_eq!(state.filtered_indexes, vec![0]);
+        state.filter("tcp_(").unwrap();
+        assert!(state.filtered_indexes.is_empty());
+    }
+
+    #[test]
+    fn fuzzy_filter_orders_items_by_score() {
+        let mut state = fixture_state();
+        state.filter_mode = FilterMode::Fuzzy;
+
+        state.filter("pin").unwrap();
+        assert_eq!(state.filtered_indexes, vec![1, 0]);
+
+        state.fuzzy_threshold = 1000;
+        state.filter("pin").unwrap();
+        assert!(state.filtered_indexes.is_empty());
+    }
+
+    #[test]
+    fn highlighter_finds_the_matched_characters() {
+        let exact = FilterHighlighter::new("tcp !move in", FilterMode::Exact).unwrap();
+        assert_eq!(exact.positions("tcp_login"), vec![0, 1, 2, 7, 8]);
+
+        let regex = FilterHighlighter::new("_(lo)g", FilterMode::Regex).unwrap();
+        assert_eq!(regex.positions("tcp_login"), vec![4, 5]);
+        assert_eq!(regex.positions("tcp_move"), Vec::<usize>::new());
+
+        let fuzzy = FilterHighlighter::new("tlg", FilterMode::Fuzzy).unwrap();
+        assert_eq!(fuzzy.positions("tcp_login"), vec![0, 4, 6]);
+
+        // multi-byte characters counted once
+        let exact = FilterHighlighter::new("é", FilterMode::Exact).unwrap();
+        assert_eq!(exact.positions("café_é"), vec![3, 5]);
+
+        assert!(FilterHighlighter::new("#login", FilterMode::Exact).is_none());
+        assert!(FilterHighlighter::new("tcp_(", FilterMode::Regex).is_none());
+    }
+
+    #[test]
+    fn tag_filter_keeps_tagged_items() {
+        let mut state = fixture_state();
+        state.items[0].tags = vec![String::from("login")];
+        state.items[2].tags = vec![String::from("login"), String::from("move")];
+
+        state.filter("#login").unwrap();
+        assert_eq!(state.filtered_indexes, vec![0, 2]);
+        state.filter("#move").unwrap();
+        assert_eq!(state.filtered_indexes, vec![2]);
+    }
+
+    #[test]
+    fn bookmarks_only_overrides_the_filter() {
+        let mut state = fixture_state();
+        state.bookmarks = HashSet::from([1, 3]);
+        state.bookmarks_only = true;
+
+        state.filter("udp").unwrap();
+        assert_eq!(state.filtered_indexes, vec![0, 2]);
+    }
+
+    #[test]
+    fn compute_stats_covers_the_filtered_items() {
+        let mut state = fixture_state();
+        state.items[2].data_size = Some(10);
+
+        state.filter("tcp").unwrap();
+        let stats = state.compute_stats();
+
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.filtered, 2);
+        assert_eq!(stats.min_size, Some(2));
+        assert_eq!(stats.max_size, Some(10));
+        assert_eq!(stats.mean_size, Some(6.0));
+        assert_eq!(stats.earliest.as_deref(), Some("2024-01-01 00:00:00"));
+        assert_eq!(stats.latest.as_deref(), Some("2024-01-01 00:00:02"));
+        assert_eq!(stats.sessions, 2);
+    }
+
+    #[test]
+    fn bucket_by_time_spreads_items_over_their_range() {
+        let mut state = fixture_state();
+        state.items[2].timestamp = String::from("2024-01-01 00:00:09");
+        state.filter("").unwrap();
+
+        // 0s, 1s and 9s over a 10s range
+        assert_eq!(state.bucket_by_time(5), vec![2, 0, 0, 0, 1]);
+        state.filter("nothing matches this").unwrap();
+        assert_eq!(state.bucket_by_time(2), vec![0, 0]);
+    }
+
+    #[test]
+    fn unchanged_filter_is_not_applied_again() {
+        let mut state = fixture_state();
+        state.filter("tcp").unwrap();
+        // left as is by the short-circuit, rebuilt otherwise
+        state.filtered_indexes.clear();
+
+        state.filter("tcp").unwrap();
+        assert!(state.filtered_indexes.is_empty());
+
+        state.sort_config.descending = true;
+        state.filter("tcp").unwrap();
+        assert_eq!(state.filtered_indexes, vec![2, 0]);
+
+        state.items.truncate(1);
+        state.invalidate_filter();
+        state.filter("tcp").unwrap();
+        assert_eq!(state.filtered_indexes, vec![0]);
+    }
+
+    #[test]
+    fn size_filter_keeps_items_in_range() {
+        let mut state = fixture_state();
+        state.items[1].data_size = Some(10);
+        state.size_filter = parse_size_range("3-").unwrap();
+
+        state.filter("").unwrap();
+        assert_eq!(state.filtered_indexes, vec![1]);
+        assert!(parse_size_range("10-2").is_err());
+        assert_eq!(parse_size_range(" ").unwrap(), None);
+    }
+
+    #[test]
+    fn apply_ui_state_restores_filter_sort_and_selection() {
+        let mut state = fixture_state();
+        let ui_state = UiState {
+            filter: "tcp".to_owned(),
+            selected_index: 5,
+            scroll_y: 0,
+            sort_config: SortConfig {
+                key: SortKey::Name,
+                descending: true,
+            },
+            wrap_navigation: false,
+            split_percent: 95,
+            filter_history: Vec::new(),
+        };
+
+        state.apply_ui_state(&ui_state).unwrap();
+
+        // tcp_move then tcp_login, the selection being clamped to the last row
+        assert_eq!(state.filtered_indexes, vec![2, 0]);
+        assert_eq!(state.selected_index, 1);
+        assert!(!state.wrap_navigation);
+        // clamped like the `<` / `>` keys do
+        assert_eq!(state.layout.split_percent, 80);
+        assert_eq!(
+            state.ui_state("tcp"),
+            UiState {
+                selected_index: 1,
+                split_percent: 80,
+                ..ui_state
+            }
+        );
+    }
+
+    #[test]
+    fn expire_toasts_drops_only_expired_ones() {
+        let mut state = fixture_state();
+        state.push_toast("gone", Duration::ZERO, Style::default());
+        state.push_toast("kept", Duration::from_secs(60), Style::default());
+        std::thread::sleep(Duration::from_millis(1));
+
+        state.expire_toasts();
+
+        let messages: Vec<&str> = state.toasts.iter().map(|t| t.message.as_str()).collect();
+        assert_eq!(messages, vec!["kept"]);
+    }
+
+    #[test]
+    fn current_screen_displays_its_path() {
+        assert_eq!(
+            CurrentScreen::Main(MainInput::Filter).to_string(),
+            "main/filter"
+        );
+        assert_eq!(
+            CurrentScreen::Editing(EditingInput::Value).to_string(),
+            "editing/value"
+        );
+        assert_eq!(
+            CurrentScreen::Exiting { dirty: true }.to_string(),
+            "exiting"
+        );
+    }
+
+    #[test]
+    fn default_state_filters_items_set_by_hand() {
+        let mut state = AppState {
+            items: vec![
+                Header::from(1, Some(1), String::from("tcp_login"), String::new()),
+                Header::from(2, Some(1), String::from("udp_ping"), String::new()),
+            ],
+            ..AppState::default()
+        };
+
+        state.filter("udp").unwrap();
+
+        assert_eq!(state.filtered_indexes, vec![1]);
+    }
+
+    #[test]
+    fn next_row_wraps_to_first() {
+        let mut state = fixture_state();
+        state.update_state(2);
+
+        state.next_row().unwrap();
+
+        assert_eq!(state.selected_index, 0);
+    }
+
+    #[test]
+    fn previous_row_wraps_to_last() {
+        let mut state = fixture_state();
+
+        state.previous_row().unwrap();
+
+        assert_eq!(state.selected_index, 2);
+    }
+
+    #[test]
+    fn navigation_stops_at_the_ends_without_wrapping() {
+        let mut state = fixture_state();
+        state.wrap_navigation = false;
+
+        state.previous_row().unwrap();
+        assert_eq!(state.selected_index, 0);
+
+        state.update_state(2);
+        state.next_row().unwrap();
+        assert_eq!(state.selected_index, 2);
+    }
+
+    #[test]
+    fn navigation_without_matches_does_not_panic() {
+        let mut state = fixture_state();
+        state.filter("nothing matches this").unwrap();
+
+        assert!(state.next_row().is_ok());
+        assert!(state.previous_row().is_ok());
+    }
+
+    #[test]
+    fn column_widths_fit_the_content_and_the_pane() {
+        let state = fixture_state();
+
+        // name, session, timestamp then size, the name column being at least 15 wide
+        assert_eq!(
+            state.column_widths(200).unwrap(),
+            vec![
+                Constraint::Min(15),
+                Constraint::Min(9),
+                Constraint::Min(21),
+                Constraint::Min(6)
+            ]
+        );
+
+        let shrunk = state.column_widths(33).unwrap();
+        let total: u16 = shrunk
+            .iter()
+            .map(|c| match c {
+                Constraint::Min(width) => *width,
+                _ => unreachable!(),
+            })
+            .sum();
+        assert!(total <= 33 - 3 - 1 - 3);
+    }
+
+    #[test]
+    fn hovering_a_cut_name_shows_it_in_full() {
+        let mut app = fixture_app();
+        app.mouse = true;
+        app.state.filter("").unwrap();
+        app.state.items[1].name = String::from("udp_ping_with_a_rather_long_name");
+        app.state.items[2].annotation = Some(String::from("two lines"));
+        app.state.table_area = Rect::new(0, 5, 40, 10);
+        app.state.name_width = Some(15);
+        let moved = |column, row| {
+            Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Moved,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+
+        // overwritten by the derived state update, which motion skips
+        app.state.layer_json = Some(String::new());
+
+        // header on row 5, then tcp_login, udp_ping... and tcp_move over two rows
+        app.handle_event(moved(3, 7)).unwrap();
+        let name = String::from("udp_ping_with_a_rather_long_name");
+        assert_eq!(app.state.hover_tooltip, Some((3, 8, name)));
+        for row in [5, 6, 8, 9, 20] {
+            app.handle_event(moved(3, row)).unwrap();
+            assert_eq!(app.state.hover_tooltip, None);
+        }
+        assert_eq!(app.state.layer_json.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn breadcrumb_clicks_navigate_back_up() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tcp_proto_messages (session INT, proto TEXT, timestamp TEXT, data TEXT);
+             INSERT INTO tcp_proto_messages VALUES (1, 'tcp_login', '2024-01-01 00:00:00', '{}');",
+        )
+        .unwrap();
+        let layer = "name = 1\n[a]\n[a.b]\nc = 1\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        let mut model = Model::from_connection(conn, layer.into(), std::path::PathBuf::new());
+        let mut state = AppState::new(&mut model).unwrap();
+        state.layer_nav_path = vec![String::from("a"), String::from("b")];
+        state.breadcrumb_area = Rect::new(10, 0, 40, 1);
+
+        // `> root > a > b `
+        assert_eq!(state.breadcrumb_depth_at(10), Some(0));
+        assert_eq!(state.breadcrumb_depth_at(17), Some(1));
+        assert_eq!(state.breadcrumb_depth_at(21), Some(2));
+        assert_eq!(state.breadcrumb_depth_at(25), None);
+
+        state.navigate_layer_to(0, &model).unwrap();
+        assert!(state.layer_nav_path.is_empty());
+        // the cursor lands on the table that was left
+        assert_eq!(state.layer_state.selected(), Some(1));
+    }
+
+    #[test]
+    fn load_layer_entries_leaves_a_table_gone_from_the_layer() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tcp_proto_messages (session INT, proto TEXT, timestamp TEXT, data TEXT);",
+        )
+        .unwrap();
+        let layer = "[a]\nx = 1\n[a.b]\nc = 1\n".parse::<DocumentMut>().unwrap();
+        let mut model = Model::from_connection(conn, layer.into(), std::path::PathBuf::new());
+        let mut state = AppState::new(&mut model).unwrap();
+        state.layer_nav_path = vec![String::from("a"), String::from("b")];
+
+        // reloaded with `b` no longer a table
+        model.layer = "[a]\nx = 1\nb = 2\n".parse::<DocumentMut>().unwrap().into();
+        state.load_layer_entries(&model).unwrap();
+        assert_eq!(state.layer_nav_path, vec![String::from("a")]);
+        assert_eq!(state.layer_state.selected(), Some(1));
+
+        model.layer = "name = 1\n".parse::<DocumentMut>().unwrap().into();
+        state.load_layer_entries(&model).unwrap();
+        assert!(state.layer_nav_path.is_empty());
+        assert_eq!(state.layer_entries.len(), 1);
+    }
+
+    #[test]
+    fn keep_selection_visible_clamps_the_table_offset() {
+        let mut state = fixture_state();
+        state.visible_rows = 2;
+        state.update_state(2);
+
+        *state.state.offset_mut() = 0;
+        state.keep_selection_visible();
+        assert_eq!(state.state.offset(), 1);
+
+        *state.state.offset_mut() = 5;
+        state.keep_selection_visible();
+        assert_eq!(state.state.offset(), 2);
+    }
+
+    #[test]
+    fn deselect_clears_the_selection_and_scroll() {
+        let mut state = fixture_state();
+        state.update_state(2);
+
+        state.deselect();
+
+        assert_eq!(state.state.selected(), None);
+        assert_eq!(state.selected_rowid(), None);
+        assert_eq!(state.scroll_state, ScrollbarState::new(2 * ITEM_HEIGHT));
+
+        state.next_row().unwrap();
+        assert_eq!(state.selected_index, 0);
+    }
+
+    #[test]
+    fn scrollbar_follows_the_selected_row() {
+        let mut state = AppState {
+            items: (1..=10)
+                .map(|rowid| Header::from(rowid, None, format!("p_{rowid}"), String::new()))
+                .collect(),
+            scroll_state: ScrollbarState::new(9 * ITEM_HEIGHT),
+            ..AppState::default()
+        };
+        state.filter("").unwrap();
+        state.update_state(0);
+
+        for _ in 0..5 {
+            state.next_row().unwrap();
+        }
+
+        assert_eq!(state.selected_index, 5);
+        assert_eq!(
+            state.scroll_state,
+            ScrollbarState::new(9 * ITEM_HEIGHT).position(state.selected_index)
+        );
+    }
+
+    #[test]
+    fn update_state_syncs_table_and_scrollbar() {
+        let mut state = fixture_state();
+
+        state.update_state(1);
+
+        assert_eq!(state.state.selected(), Some(1));
+        assert_eq!(
+            state.scroll_state,
+            ScrollbarState::new(2 * ITEM_HEIGHT).position(ITEM_HEIGHT)
+        );
+    }
 }
@@ -1,17 +1,32 @@
 use anyhow::{Context, Error, Result};
 use std::collections::HashMap;
+use std::fmt;
+use toml_edit::{value, Item};
+use unicode_width::UnicodeWidthStr;
 
-#[derive(Hash, PartialEq, Eq, Debug)]
+#[derive(Hash, PartialEq, Eq, Debug, Clone)]
 pub enum InputId {
     Filter,
+    Command,
+    Annotation,
+    SqlQuery,
+    SizeFilter,
     Key,
     Value,
+    // fields of the new proto popup
+    NewSession,
+    NewName,
+    NewTimestamp,
+    NewData,
+    Custom(String), // runtime-defined field, see `InputArena::register`
 }
 
 #[derive(Clone, Debug)]
 pub enum MainInput {
     None,
     Filter,
+    Layer,
+    Command, // command palette, opened with `:`
 }
 
 impl TryFrom<MainInput> for InputId {
@@ -19,6 +34,7 @@ impl TryFrom<MainInput> for InputId {
     fn try_from(input: MainInput) -> Result<Self, Self::Error> {
         match input {
             MainInput::Filter => Ok(InputId::Filter),
+            MainInput::Command => Ok(InputId::Command),
             _ => Err(Error::msg("Cannot convert into an input.")),
         }
     }
@@ -29,6 +45,7 @@ impl TryFrom<&MainInput> for &InputId {
     fn try_from(input: &MainInput) -> Result<Self, Self::Error> {
         match *input {
             MainInput::Filter => Ok(&InputId::Filter),
+            MainInput::Command => Ok(&InputId::Command),
             _ => Err(Error::msg("Cannot convert into an input.")),
         }
     }
@@ -58,31 +75,289 @@ impl From<&EditingInput> for &InputId {
     }
 }
 
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ValueType {
+    #[default]
+    Str,
+    Int,
+    Float,
+    Bool,
+}
+
+impl ValueType {
+    pub fn next(&self) -> Self {
+        match self {
+            ValueType::Str => ValueType::Int,
+            ValueType::Int => ValueType::Float,
+            ValueType::Float => ValueType::Bool,
+            ValueType::Bool => ValueType::Str,
+        }
+    }
+
+    pub fn parse(&self, raw: &str) -> Result<Item> {
+        match self {
+            ValueType::Str => Ok(value(raw)),
+            ValueType::Int => Ok(value(
+                raw.trim()
+                    .parse::<i64>()
+                    .context(format!("`{raw}` is not a valid integer"))?,
+            )),
+            ValueType::Float => Ok(value(
+                raw.trim()
+                    .parse::<f64>()
+                    .context(format!("`{raw}` is not a valid float"))?,
+            )),
+            ValueType::Bool => Ok(value(
+                raw.trim()
+                    .parse::<bool>()
+                    .context(format!("`{raw}` is not a valid boolean"))?,
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueType::Str => write!(f, "String"),
+            ValueType::Int => write!(f, "Integer"),
+            ValueType::Float => write!(f, "Float"),
+            ValueType::Bool => write!(f, "Boolean"),
+        }
+    }
+}
+
+// confirmed values kept by an input, older ones being dropped
+pub const HISTORY_LIMIT: usize = 20;
+
 pub struct InputField {
     content: String,
     is_active: bool,
+    completion: Option<(String, usize)>, // typed prefix and index of the proposed match
+    max_length: Option<usize>,           // in UTF-8 bytes, characters not fitting being dropped
+    multiline: bool,                     // Enter types a newline instead of confirming
+    history: Vec<String>,                // confirmed values, the most recent last
+    history_index: Option<usize>,        // entry being recalled with Up/Down
 }
 
 impl InputField {
-    pub fn new() -> Result<Self> {
-        Ok(InputField {
+    pub fn new() -> Self {
+        InputField {
             content: String::new(),
             is_active: false,
-        })
+            completion: None,
+            max_length: None,
+            multiline: false,
+            history: Vec::new(),
+            history_index: None,
+        }
+    }
+
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    // whether the field has the focus, see `InputArena::set_active`
+    pub fn is_active(&self) -> bool {
+        self.is_active
+    }
+
+    pub fn is_multiline(&self) -> bool {
+        self.multiline
+    }
+
+    pub fn toggle_multiline(&mut self) {
+        self.multiline = !self.multiline;
+    }
+
+    // columns and line of the insertion point, text always being typed at the end
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let line = self.content.split('\n').count() - 1;
+        let last = self.content.rsplit('\n').next().unwrap_or_default();
+        (last.width(), line)
+    }
+
+    // whether `value` can be typed without going over the max length
+    pub fn fits(&self, value: char) -> bool {
+        self.max_length
+            .is_none_or(|max_length| self.content.len() + value.len_utf8() <= max_length)
+    }
+
+    // replaces the content by the first candidate starting with what has been typed,
+    // calling it again cycles through the other matching candidates
+    pub fn autocomplete(&mut self, candidates: &[String]) {
+        let (prefix, next) = match self.completion.take() {
+            Some((prefix, index)) => (prefix, index + 1),
+            None => (self.content.clone(), 0),
+        };
+
+        let matches: Vec<&String> = candidates
+            .iter()
+            .filter(|candidate| candidate.starts_with(&prefix))
+            .collect();
+        if matches.is_empty() {
+            return;
+        }
+
+        let index = next % matches.len();
+        self.content = matches[index].clone();
+        self.completion = Some((prefix, index));
+    }
+
+    pub fn clear(&mut self) {
+        self.content.clear();
+        self.completion = None;
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn set_history(&mut self, mut history: Vec<String>) {
+        let overflow = history.len().saturating_sub(HISTORY_LIMIT);
+        history.drain(..overflow);
+        self.history = history;
+        self.history_index = None;
+    }
+
+    pub fn clear_history(&mut self) {
+        self.set_history(Vec::new());
+    }
+
+    // moves the content to the end of the history, where it is no longer duplicated
+    pub fn push_history(&mut self) {
+        self.history_index = None;
+        if self.content.is_empty() {
+            return;
+        }
+        self.history.retain(|entry| *entry != self.content);
+        self.history.push(self.content.clone());
+        let overflow = self.history.len().saturating_sub(HISTORY_LIMIT);
+        self.history.drain(..overflow);
+    }
+
+    pub fn history_previous(&mut self) {
+        let index = match self.history_index {
+            Some(index) => index.saturating_sub(1),
+            None => match self.history.len().checked_sub(1) {
+                Some(last) => last,
+                None => return,
+            },
+        };
+        self.history_index = Some(index);
+        self.content = self.history[index].clone();
+    }
+
+    // going past the most recent entry gives back an empty input
+    pub fn history_next(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.history_index = Some(index + 1);
+            self.content = self.history[index + 1].clone();
+        } else {
+            self.history_index = None;
+            self.content.clear();
+        }
+    }
+}
+
+// multi-line input, text is always typed at the end of the focused line
+pub struct MultilineField {
+    lines: Vec<String>,
+    line: usize, // index of the focused line
+}
+
+impl MultilineField {
+    pub fn new() -> Self {
+        MultilineField {
+            lines: vec![String::new()],
+            line: 0,
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn push(&mut self, value: char) {
+        self.lines[self.line].push(value);
+    }
+
+    // removes the last character, or the focused line itself once it is empty
+    pub fn pop(&mut self) {
+        if self.lines[self.line].pop().is_none() && self.lines.len() > 1 {
+            self.lines.remove(self.line);
+            self.line = self.line.saturating_sub(1);
+        }
+    }
+
+    pub fn insert_line(&mut self) {
+        self.line += 1;
+        self.lines.insert(self.line, String::new());
+    }
+
+    pub fn previous_line(&mut self) {
+        self.line = self.line.saturating_sub(1);
+    }
+
+    pub fn next_line(&mut self) {
+        self.line = (self.line + 1).min(self.lines.len() - 1);
     }
 }
 
 pub struct InputArena {
     fields: HashMap<InputId, InputField>,
+    multiline_fields: HashMap<InputId, MultilineField>,
+}
+
+impl Default for InputArena {
+    fn default() -> Self {
+        InputArena::new()
+    }
 }
 
 impl InputArena {
-    pub fn new() -> Result<Self> {
-        let mut fields = HashMap::with_capacity(3); // hardcoded for now
-        fields.insert(InputId::Filter, InputField::new()?);
-        fields.insert(InputId::Key, InputField::new()?);
-        fields.insert(InputId::Value, InputField::new()?);
-        Ok(InputArena { fields })
+    pub fn new() -> Self {
+        let mut fields = HashMap::with_capacity(10); // hardcoded for now
+        fields.insert(InputId::Filter, InputField::new().with_max_length(100));
+        fields.insert(InputId::Command, InputField::new());
+        fields.insert(InputId::Annotation, InputField::new());
+        fields.insert(InputId::SizeFilter, InputField::new());
+        fields.insert(InputId::Key, InputField::new().with_max_length(255));
+        fields.insert(InputId::Value, InputField::new().with_max_length(255));
+        fields.insert(InputId::NewSession, InputField::new());
+        fields.insert(InputId::NewName, InputField::new());
+        fields.insert(InputId::NewTimestamp, InputField::new());
+        fields.insert(InputId::NewData, InputField::new());
+        let mut multiline_fields = HashMap::with_capacity(1);
+        multiline_fields.insert(InputId::SqlQuery, MultilineField::new());
+        InputArena {
+            fields,
+            multiline_fields,
+        }
+    }
+
+    pub fn get_lines(&self, k: &InputId) -> Result<&MultilineField> {
+        self.multiline_fields
+            .get(k)
+            .context(format!("Cannot find {k:?} in the input arena"))
+    }
+
+    pub fn get_lines_mut(&mut self, k: &InputId) -> Result<&mut MultilineField> {
+        self.multiline_fields
+            .get_mut(k)
+            .context(format!("Cannot find {k:?} in the input arena"))
     }
 
     pub fn get(&self, k: &InputId) -> Result<&InputField> {
@@ -100,18 +375,242 @@ impl InputArena {
     }
 
     pub fn get_mut(&mut self, k: &InputId) -> Result<&mut InputField> {
+        if !self.fields.contains_key(k) {
+            self.fields.insert(k.clone(), InputField::new());
+        }
         self.fields
             .get_mut(k)
             .context(format!("Cannot find {k:?} in the input arena"))
     }
 
+    // declares a field defined at runtime, registering a name twice gives back the same field
+    pub fn register(&mut self, name: &str) -> &InputId {
+        let id = InputId::Custom(name.to_owned());
+        self.fields
+            .entry(id.clone())
+            .or_insert_with(InputField::new);
+        self.fields
+            .get_key_value(&id)
+            .map(|(k, _)| k)
+            .expect("the field was just inserted")
+    }
+
+    // focuses `k`, any other field losing the focus
+    pub fn set_active(&mut self, k: &InputId) -> Result<()> {
+        self.clear_active();
+        self.get_mut(k)?.is_active = true;
+        Ok(())
+    }
+
+    pub fn clear_active(&mut self) {
+        self.fields
+            .values_mut()
+            .for_each(|field| field.is_active = false);
+    }
+
     pub fn value_pop(&mut self, k: &InputId) -> Result<()> {
-        self.get_mut(k)?.content.pop();
+        let field = self.get_mut(k)?;
+        field.content.pop();
+        field.completion = None;
+        Ok(())
+    }
+
+    pub fn reset_field(&mut self, k: &InputId) -> Result<()> {
+        self.get_mut(k)?.clear();
         Ok(())
     }
 
+    // resets every field of a form, leaving the fields of the other screens alone
+    pub fn reset_all(&mut self, ks: &[InputId]) -> Result<()> {
+        ks.iter().try_for_each(|k| self.reset_field(k))
+    }
+
+    pub fn fits(&self, k: &InputId, value: char) -> Result<bool> {
+        Ok(self.get(k)?.fits(value))
+    }
+
+    // does nothing when `value` does not fit in the field
     pub fn value_push(&mut self, k: &InputId, value: char) -> Result<()> {
-        self.get_mut(k)?.content.push(value);
+        let field = self.get_mut(k)?;
+        if !field.fits(value) {
+            return Ok(());
+        }
+        field.content.push(value);
+        field.completion = None;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autocomplete_cycles_through_matching_candidates() {
+        let candidates = vec![
+            String::from("alias"),
+            String::from("name"),
+            String::from("alert"),
+        ];
+        let mut arena = InputArena::new();
+        arena.value_push(&InputId::Key, 'a').unwrap();
+
+        let field = arena.get_mut(&InputId::Key).unwrap();
+        field.autocomplete(&candidates);
+        assert_eq!(field.content, "alias");
+        field.autocomplete(&candidates);
+        assert_eq!(field.content, "alert");
+        field.autocomplete(&candidates);
+        assert_eq!(field.content, "alias");
+
+        arena.reset_field(&InputId::Key).unwrap();
+        arena.value_push(&InputId::Key, 'z').unwrap();
+        let field = arena.get_mut(&InputId::Key).unwrap();
+        field.autocomplete(&candidates);
+        assert_eq!(field.content, "z");
+    }
+
+    #[test]
+    fn set_active_focuses_a_single_field() {
+        let mut arena = InputArena::new();
+        arena.set_active(&InputId::Key).unwrap();
+        arena.set_active(&InputId::Value).unwrap();
+
+        assert!(arena.get(&InputId::Value).unwrap().is_active());
+        assert!(!arena.get(&InputId::Key).unwrap().is_active());
+
+        arena.clear_active();
+        assert!(!arena.get(&InputId::Value).unwrap().is_active());
+    }
+
+    #[test]
+    fn value_push_stops_at_the_max_length() {
+        let mut arena = InputArena::new();
+        for _ in 0..120 {
+            arena.value_push(&InputId::Filter, 'a').unwrap();
+        }
+
+        assert_eq!(arena.get_content(&InputId::Filter).unwrap().len(), 100);
+        assert!(!arena.fits(&InputId::Filter, 'a').unwrap());
+        assert!(arena.fits(&InputId::Command, 'a').unwrap());
+    }
+
+    #[test]
+    fn value_push_drops_a_character_going_over_the_max_length() {
+        let mut arena = InputArena::new();
+        for _ in 0..99 {
+            arena.value_push(&InputId::Filter, 'a').unwrap();
+        }
+
+        // three bytes, one too many
+        arena.value_push(&InputId::Filter, '€').unwrap();
+        assert_eq!(arena.get_content(&InputId::Filter).unwrap().len(), 99);
+        arena.value_push(&InputId::Filter, 'a').unwrap();
+        assert_eq!(arena.get_content(&InputId::Filter).unwrap().len(), 100);
+    }
+
+    #[test]
+    fn history_is_deduplicated_limited_and_recalled() {
+        let mut field = InputField::new();
+        for i in 0..HISTORY_LIMIT + 5 {
+            field.content = format!("filter {i}");
+            field.push_history();
+        }
+        field.content = String::from("filter 10");
+        field.push_history();
+
+        assert_eq!(field.history().len(), HISTORY_LIMIT);
+        assert_eq!(field.history().first().unwrap(), "filter 5");
+        assert_eq!(field.history().last().unwrap(), "filter 10");
+
+        field.clear();
+        field.history_previous();
+        assert_eq!(field.content, "filter 10");
+        field.history_previous();
+        assert_eq!(field.content, "filter 24");
+        field.history_next();
+        assert_eq!(field.content, "filter 10");
+        field.history_next();
+        assert_eq!(field.content, "");
+    }
+
+    #[test]
+    fn cursor_position_follows_the_last_line() {
+        let mut field = InputField::new();
+        assert_eq!(field.cursor_position(), (0, 0));
+
+        field.content = String::from("ab\n日本");
+        assert_eq!(field.cursor_position(), (4, 1));
+        field.content.push('\n');
+        assert_eq!(field.cursor_position(), (0, 2));
+    }
+
+    #[test]
+    fn value_pop_removes_the_last_characters() {
+        let mut arena = InputArena::new();
+        for value in "hello".chars() {
+            arena.value_push(&InputId::Filter, value).unwrap();
+        }
+
+        arena.value_pop(&InputId::Filter).unwrap();
+        arena.value_pop(&InputId::Filter).unwrap();
+
+        assert_eq!(arena.get_content(&InputId::Filter).unwrap(), "hel");
+    }
+
+    #[test]
+    fn value_pop_removes_a_whole_multi_byte_character() {
+        let mut arena = InputArena::new();
+        arena.value_push(&InputId::Filter, '🦀').unwrap();
+
+        arena.value_pop(&InputId::Filter).unwrap();
+
+        assert_eq!(arena.get_content(&InputId::Filter).unwrap(), "");
+    }
+
+    #[test]
+    fn reset_field_empties_a_filled_field() {
+        let mut arena = InputArena::new();
+        for value in "login".chars() {
+            arena.value_push(&InputId::Key, value).unwrap();
+        }
+
+        arena.reset_field(&InputId::Key).unwrap();
+
+        assert_eq!(arena.get_content(&InputId::Key).unwrap(), "");
+    }
+
+    #[test]
+    fn history_previous_without_history_keeps_the_content() {
+        let mut field = InputField::new();
+        field.content = String::from("tcp");
+
+        field.history_previous();
+
+        assert_eq!(field.content, "tcp");
+    }
+
+    #[test]
+    fn register_gives_back_the_same_field_for_a_name() {
+        let mut arena = InputArena::new();
+        let id = arena.register("session").clone();
+        arena.value_push(&id, '2').unwrap();
+
+        assert_eq!(arena.register("session"), &id);
+        assert_eq!(arena.get_content(&id).unwrap(), "2");
+    }
+
+    #[test]
+    fn reset_all_empties_the_given_fields() {
+        let mut arena = InputArena::new();
+        arena.value_push(&InputId::Filter, 'a').unwrap();
+        arena.value_push(&InputId::Key, 'b').unwrap();
+        arena.value_push(&InputId::Value, 'c').unwrap();
+
+        arena.reset_all(&[InputId::Key, InputId::Value]).unwrap();
+
+        assert_eq!(arena.get_content(&InputId::Filter).unwrap(), "a");
+        assert_eq!(arena.get_content(&InputId::Key).unwrap(), "");
+        assert_eq!(arena.get_content(&InputId::Value).unwrap(), "");
+    }
+}
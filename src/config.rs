@@ -0,0 +1,82 @@
+use crate::layer::LayerFormat;
+use crate::model::DATA_CACHE_SIZE;
+use ratatui::{
+    crossterm::event::KeyCode,
+    style::{palette::tailwind, Color},
+};
+
+pub(crate) const DEFAULT_PAGE_SIZE: usize = 20;
+pub(crate) const DEFAULT_TICK_RATE: u64 = 100;
+pub(crate) const DEFAULT_FPS: u32 = 30;
+pub(crate) const DEFAULT_FUZZY_THRESHOLD: i64 = 0;
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub accent: Color,    // color of the focused texts
+    pub highlight: Color, // color of the selected row
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            accent: Color::Green,
+            highlight: tailwind::SLATE.c400,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    pub quit: KeyCode,
+    pub filter: KeyCode,
+    pub edit: KeyCode,
+    pub refresh: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            quit: KeyCode::Char('q'),
+            filter: KeyCode::Char('f'),
+            edit: KeyCode::Char('e'),
+            refresh: KeyCode::Char('r'),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub db_path: std::path::PathBuf,
+    pub layer_path: std::path::PathBuf,
+    pub layer_format: Option<LayerFormat>, // deduced from the layer extension if not set
+    pub page_size: usize,                  // number of rows skipped by PageUp / PageDown
+    pub theme: Theme,
+    pub keybindings: KeyBindings,
+    pub tick_rate: u64,                     // in milliseconds
+    pub fps: u32,                           // most frames drawn per second
+    pub mouse: bool,                        // whether mouse events are captured
+    pub read_write: bool,                   // whether the database can be written to
+    pub create_layer: bool,                 // whether a missing layer file is created empty
+    pub cache_size: std::num::NonZeroUsize, // protos whose data is kept in memory
+    pub fuzzy_threshold: i64,               // score a name must beat in fuzzy filter mode
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            db_path: std::path::PathBuf::new(),
+            layer_path: std::path::PathBuf::new(),
+            layer_format: None,
+            page_size: DEFAULT_PAGE_SIZE,
+            theme: Theme::default(),
+            keybindings: KeyBindings::default(),
+            tick_rate: DEFAULT_TICK_RATE,
+            fps: DEFAULT_FPS,
+            mouse: true,
+            read_write: false,
+            create_layer: false,
+            cache_size: DATA_CACHE_SIZE,
+            fuzzy_threshold: DEFAULT_FUZZY_THRESHOLD,
+        }
+    }
+}
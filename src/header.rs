@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Header {
+    pub rowid: usize,
+    pub session_id: Option<usize>,
+    pub name: String,
+    pub timestamp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_size: Option<usize>, // in bytes
+}
+
+impl Header {
+    pub fn from(rowid: usize, session_id: Option<usize>, name: String, timestamp: String) -> Self {
+        Header {
+            rowid,
+            session_id,
+            name,
+            timestamp,
+            annotation: None,
+            tags: Vec::new(),
+            data_size: None,
+        }
+    }
+
+    // expects the `rowid, session, proto, timestamp, annotation, tags, data_size`
+    // columns, in that order, tags being comma separated
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let mut tags: Vec<String> = row
+            .get::<_, Option<String>>(5)?
+            .map(|tags| tags.split(',').map(str::to_owned).collect())
+            .unwrap_or_default();
+        tags.sort();
+
+        Ok(Header {
+            annotation: row.get(4)?,
+            tags,
+            data_size: row.get(6)?,
+            ..Header::from(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)
+        })
+    }
+
+    // the name fitting in `max_width` terminal columns, an ellipsis replacing the end
+    // of a longer one. Cut between graphemes so no accent is left alone
+    pub fn display_name(&self, max_width: usize) -> Cow<'_, str> {
+        if self.name.width() <= max_width {
+            return Cow::Borrowed(&self.name);
+        }
+        let Some(budget) = max_width.checked_sub(1) else {
+            return Cow::Borrowed("");
+        };
+        let mut width = 0;
+        let mut end = 0;
+        for (i, grapheme) in self.name.grapheme_indices(true) {
+            width += grapheme.width();
+            if width > budget {
+                break;
+            }
+            end = i + grapheme.len();
+        }
+        Cow::Owned(format!("{}…", &self.name[..end]))
+    }
+}
+
+// `rowid<TAB>session<TAB>name<TAB>timestamp`, a missing session being written NULL
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let session = self
+            .session_id
+            .map_or_else(|| String::from("NULL"), |s| s.to_string());
+        write!(
+            f,
+            "{}\t{session}\t{}\t{}",
+            self.rowid, self.name, self.timestamp
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_displays_as_tab_separated_fields() {
+        let header = Header::from(3, None, String::from("tcp_login"), String::from("ts"));
+        assert_eq!(header.to_string(), "3\tNULL\ttcp_login\tts");
+
+        let header = Header::from(4, Some(2), String::from("udp"), String::from("ts"));
+        assert_eq!(header.to_string(), "4\t2\tudp\tts");
+    }
+
+    #[test]
+    fn display_name_cuts_long_names() {
+        let header = |name: &str| Header::from(1, None, name.to_owned(), String::new());
+
+        assert_eq!(header("tcp_login").display_name(9), "tcp_login");
+        assert_eq!(header("tcp_login").display_name(6), "tcp_l…");
+        // two columns each
+        assert_eq!(header("日本語").display_name(4), "日…");
+        // `e` and its combining acute accent kept together
+        assert_eq!(header("cafe\u{301}_bar").display_name(6), "cafe\u{301}_…");
+        assert_eq!(header("tcp_login").display_name(0), "");
+    }
+
+    #[test]
+    fn header_json_round_trip() {
+        let header = Header::from(
+            42,
+            Some(3),
+            String::from("tcp_proto"),
+            String::from("2024-01-01 00:00:00"),
+        );
+
+        let json = serde_json::to_string(&header).unwrap();
+        let decoded: Header = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(decoded, header);
+    }
+
+    #[test]
+    fn header_json_natural_types() {
+        let header = Header::from(1, None, String::from("udp"), String::from("now"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&header).unwrap()).unwrap();
+
+        assert!(json["rowid"].is_number());
+        assert!(json["session_id"].is_null());
+        assert!(json["name"].is_string());
+        assert!(json["timestamp"].is_string());
+    }
+}
@@ -0,0 +1,438 @@
+use anyhow::{Context, Error, Result};
+use serde_json::Value as Json;
+use serde_yaml::Value as Yaml;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use toml_edit::{table, Array, DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LayerFormat {
+    #[default]
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl LayerFormat {
+    // anything without a known extension is considered TOML
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => LayerFormat::Json,
+            Some("yaml" | "yml") => LayerFormat::Yaml,
+            _ => LayerFormat::Toml,
+        }
+    }
+}
+
+impl FromStr for LayerFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "toml" => Ok(LayerFormat::Toml),
+            "json" => Ok(LayerFormat::Json),
+            "yaml" | "yml" => Ok(LayerFormat::Yaml),
+            other => Err(Error::msg(format!(
+                "Unknown layer format `{other}`, expected toml, json or yaml"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for LayerFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayerFormat::Toml => write!(f, "TOML"),
+            LayerFormat::Json => write!(f, "JSON"),
+            LayerFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+// read and write access to a layer whatever its format, values being exchanged as
+// `toml_edit::Item` and keys addressed by their path from the root table
+pub trait LayerAccess {
+    fn get(&self, path: &[&str]) -> Option<Item>;
+    fn set(&mut self, path: &[&str], value: Item) -> Result<()>;
+    fn delete(&mut self, path: &[&str]) -> Option<Item>;
+    fn keys(&self, path: &[&str]) -> Result<Vec<String>>;
+    // not named `to_string` to avoid clashing with `ToString` on the implementors
+    fn to_layer_string(&self) -> Result<String>;
+}
+
+fn missing_table(path: &[&str]) -> Error {
+    Error::msg(format!(
+        "Cannot find table `{}` in the layer",
+        path.join(".")
+    ))
+}
+
+impl LayerAccess for DocumentMut {
+    fn get(&self, path: &[&str]) -> Option<Item> {
+        let mut item = self.as_item();
+        for key in path {
+            item = item.get(key)?;
+        }
+        Some(item.clone())
+    }
+
+    fn set(&mut self, path: &[&str], value: Item) -> Result<()> {
+        let (last, parents) = path.split_last().context("Key cannot be empty")?;
+        let mut current: &mut dyn TableLike = self.as_table_mut();
+        for (depth, key) in parents.iter().enumerate() {
+            current = current
+                .entry(key)
+                .or_insert(table())
+                .as_table_like_mut()
+                .ok_or_else(|| missing_table(&parents[..=depth]))?;
+        }
+        current.insert(last, value);
+        Ok(())
+    }
+
+    fn delete(&mut self, path: &[&str]) -> Option<Item> {
+        let (last, parents) = path.split_last()?;
+        let mut current: &mut dyn TableLike = self.as_table_mut();
+        for key in parents {
+            current = current.get_mut(key)?.as_table_like_mut()?;
+        }
+        current.remove(last)
+    }
+
+    fn keys(&self, path: &[&str]) -> Result<Vec<String>> {
+        let mut current: &dyn TableLike = self.as_table();
+        for key in path {
+            current = current
+                .get(key)
+                .and_then(Item::as_table_like)
+                .ok_or_else(|| missing_table(path))?;
+        }
+        Ok(current.iter().map(|(k, _)| k.to_owned()).collect())
+    }
+
+    fn to_layer_string(&self) -> Result<String> {
+        Ok(self.to_string())
+    }
+}
+
+impl LayerAccess for Json {
+    fn get(&self, path: &[&str]) -> Option<Item> {
+        let mut current = self;
+        for key in path {
+            current = current.get(key)?;
+        }
+        Some(json_to_item(current))
+    }
+
+    fn set(&mut self, path: &[&str], value: Item) -> Result<()> {
+        let (last, parents) = path.split_last().context("Key cannot be empty")?;
+        let mut current = self.as_object_mut().ok_or_else(|| missing_table(&[]))?;
+        for (depth, key) in parents.iter().enumerate() {
+            current = current
+                .entry(key.to_string())
+                .or_insert_with(|| Json::Object(Default::default()))
+                .as_object_mut()
+                .ok_or_else(|| missing_table(&parents[..=depth]))?;
+        }
+        current.insert(last.to_string(), item_to_json(&value));
+        Ok(())
+    }
+
+    fn delete(&mut self, path: &[&str]) -> Option<Item> {
+        let (last, parents) = path.split_last()?;
+        let mut current = self.as_object_mut()?;
+        for key in parents {
+            current = current.get_mut(*key)?.as_object_mut()?;
+        }
+        current.remove(*last).map(|removed| json_to_item(&removed))
+    }
+
+    fn keys(&self, path: &[&str]) -> Result<Vec<String>> {
+        let mut current = self;
+        for key in path {
+            current = current.get(key).ok_or_else(|| missing_table(path))?;
+        }
+        Ok(current
+            .as_object()
+            .ok_or_else(|| missing_table(path))?
+            .keys()
+            .cloned()
+            .collect())
+    }
+
+    fn to_layer_string(&self) -> Result<String> {
+        Ok(format!("{}\n", serde_json::to_string_pretty(self)?))
+    }
+}
+
+// YAML values go through their JSON equivalent, the layer only holding
+// string keys and plain values
+impl LayerAccess for Yaml {
+    fn get(&self, path: &[&str]) -> Option<Item> {
+        let mut current = self;
+        for key in path {
+            current = current.get(key)?;
+        }
+        Some(json_to_item(&serde_json::to_value(current).ok()?))
+    }
+
+    fn set(&mut self, path: &[&str], value: Item) -> Result<()> {
+        let (last, parents) = path.split_last().context("Key cannot be empty")?;
+        let mut current = self.as_mapping_mut().ok_or_else(|| missing_table(&[]))?;
+        for (depth, key) in parents.iter().enumerate() {
+            current = current
+                .entry(Yaml::from(*key))
+                .or_insert_with(|| Yaml::Mapping(Default::default()))
+                .as_mapping_mut()
+                .ok_or_else(|| missing_table(&parents[..=depth]))?;
+        }
+        current.insert(
+            Yaml::from(*last),
+            serde_yaml::to_value(item_to_json(&value))?,
+        );
+        Ok(())
+    }
+
+    fn delete(&mut self, path: &[&str]) -> Option<Item> {
+        let (last, parents) = path.split_last()?;
+        let mut current = self.as_mapping_mut()?;
+        for key in parents {
+            current = current.get_mut(*key)?.as_mapping_mut()?;
+        }
+        let removed = current.remove(*last)?;
+        Some(json_to_item(&serde_json::to_value(removed).ok()?))
+    }
+
+    fn keys(&self, path: &[&str]) -> Result<Vec<String>> {
+        let mut current = self;
+        for key in path {
+            current = current.get(key).ok_or_else(|| missing_table(path))?;
+        }
+        current
+            .as_mapping()
+            .ok_or_else(|| missing_table(path))?
+            .keys()
+            .map(|key| {
+                key.as_str()
+                    .map(str::to_owned)
+                    .with_context(|| format!("Key `{key:?}` is not a string"))
+            })
+            .collect()
+    }
+
+    fn to_layer_string(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+// a parsed layer file, kept in its original format so saving it preserves the format
+#[derive(Clone, Debug)]
+pub enum LayerRepr {
+    Toml(DocumentMut),
+    Json(Json),
+    Yaml(Yaml),
+}
+
+impl LayerRepr {
+    pub fn parse(contents: &str, format: LayerFormat) -> Result<Self> {
+        match format {
+            LayerFormat::Toml => Ok(LayerRepr::Toml(
+                contents.parse().context("Unable to parse TOML")?,
+            )),
+            LayerFormat::Json => Ok(LayerRepr::Json(
+                serde_json::from_str(contents).context("Unable to parse JSON")?,
+            )),
+            LayerFormat::Yaml => Ok(LayerRepr::Yaml(
+                serde_yaml::from_str(contents).context("Unable to parse YAML")?,
+            )),
+        }
+    }
+
+    // layer without any key, written when creating a missing layer file
+    pub fn empty(format: LayerFormat) -> Self {
+        match format {
+            LayerFormat::Toml => LayerRepr::Toml(DocumentMut::new()),
+            LayerFormat::Json => LayerRepr::Json(Json::Object(serde_json::Map::new())),
+            LayerFormat::Yaml => LayerRepr::Yaml(Yaml::Mapping(serde_yaml::Mapping::new())),
+        }
+    }
+
+    pub fn format(&self) -> LayerFormat {
+        match self {
+            LayerRepr::Toml(_) => LayerFormat::Toml,
+            LayerRepr::Json(_) => LayerFormat::Json,
+            LayerRepr::Yaml(_) => LayerFormat::Yaml,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<Json> {
+        match self {
+            LayerRepr::Toml(layer) => Ok(item_to_json(layer.as_item())),
+            LayerRepr::Json(layer) => Ok(layer.clone()),
+            LayerRepr::Yaml(layer) => Ok(serde_json::to_value(layer)?),
+        }
+    }
+
+    fn access(&self) -> &dyn LayerAccess {
+        match self {
+            LayerRepr::Toml(layer) => layer,
+            LayerRepr::Json(layer) => layer,
+            LayerRepr::Yaml(layer) => layer,
+        }
+    }
+
+    fn access_mut(&mut self) -> &mut dyn LayerAccess {
+        match self {
+            LayerRepr::Toml(layer) => layer,
+            LayerRepr::Json(layer) => layer,
+            LayerRepr::Yaml(layer) => layer,
+        }
+    }
+}
+
+impl From<DocumentMut> for LayerRepr {
+    fn from(layer: DocumentMut) -> Self {
+        LayerRepr::Toml(layer)
+    }
+}
+
+impl LayerAccess for LayerRepr {
+    fn get(&self, path: &[&str]) -> Option<Item> {
+        self.access().get(path)
+    }
+
+    fn set(&mut self, path: &[&str], value: Item) -> Result<()> {
+        self.access_mut().set(path, value)
+    }
+
+    fn delete(&mut self, path: &[&str]) -> Option<Item> {
+        self.access_mut().delete(path)
+    }
+
+    fn keys(&self, path: &[&str]) -> Result<Vec<String>> {
+        self.access().keys(path)
+    }
+
+    fn to_layer_string(&self) -> Result<String> {
+        self.access().to_layer_string()
+    }
+}
+
+// JSON objects become tables, nulls have no TOML equivalent and are dropped
+pub fn json_to_item(json: &Json) -> Item {
+    match json {
+        Json::Object(map) => {
+            let mut table = Table::new();
+            for (key, value) in map {
+                table.insert(key, json_to_item(value));
+            }
+            Item::Table(table)
+        }
+        other => json_to_value(other).map(Item::Value).unwrap_or(Item::None),
+    }
+}
+
+fn json_to_value(json: &Json) -> Option<Value> {
+    Some(match json {
+        Json::Null => return None,
+        Json::Bool(b) => Value::from(*b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::from(i),
+            None => Value::from(n.as_f64()?),
+        },
+        Json::String(s) => Value::from(s.as_str()),
+        Json::Array(items) => {
+            Value::Array(items.iter().filter_map(json_to_value).collect::<Array>())
+        }
+        Json::Object(map) => Value::InlineTable(
+            map.iter()
+                .filter_map(|(k, v)| Some((k.as_str(), json_to_value(v)?)))
+                .collect::<InlineTable>(),
+        ),
+    })
+}
+
+pub fn item_to_json(item: &Item) -> Json {
+    match item {
+        Item::None => Json::Null,
+        Item::Value(value) => value_to_json(value),
+        Item::Table(table) => table_to_json(table),
+        Item::ArrayOfTables(tables) => Json::Array(tables.iter().map(table_to_json).collect()),
+    }
+}
+
+fn table_to_json(table: &Table) -> Json {
+    Json::Object(
+        table
+            .iter()
+            .map(|(k, v)| (k.to_owned(), item_to_json(v)))
+            .collect(),
+    )
+}
+
+fn value_to_json(value: &Value) -> Json {
+    match value {
+        Value::String(s) => Json::from(s.value().as_str()),
+        Value::Integer(i) => Json::from(*i.value()),
+        Value::Float(f) => Json::from(*f.value()),
+        Value::Boolean(b) => Json::from(*b.value()),
+        Value::Datetime(d) => Json::from(d.value().to_string()),
+        Value::Array(items) => Json::Array(items.iter().map(value_to_json).collect()),
+        Value::InlineTable(table) => Json::Object(
+            table
+                .iter()
+                .map(|(k, v)| (k.to_owned(), value_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_layer_reads_and_writes_nested_keys() {
+        let mut layer = LayerRepr::parse(
+            r#"{"name": "x", "section": {"foo": 1, "tags": ["a", "b"]}}"#,
+            LayerFormat::Json,
+        )
+        .unwrap();
+
+        assert_eq!(layer.keys(&[]).unwrap(), vec!["name", "section"]);
+        assert_eq!(layer.keys(&["section"]).unwrap(), vec!["foo", "tags"]);
+        assert_eq!(
+            layer.get(&["section", "foo"]).unwrap().as_integer(),
+            Some(1)
+        );
+        assert!(layer.keys(&["name"]).is_err());
+
+        layer
+            .set(&["section", "sub", "bar"], toml_edit::value(true))
+            .unwrap();
+        assert!(layer.delete(&["name"]).is_some());
+
+        let saved: Json = serde_json::from_str(&layer.to_layer_string().unwrap()).unwrap();
+        assert_eq!(
+            saved,
+            serde_json::json!({"section": {"foo": 1, "tags": ["a", "b"], "sub": {"bar": true}}})
+        );
+    }
+
+    #[test]
+    fn toml_layer_reads_and_writes_nested_keys() {
+        let mut layer =
+            LayerRepr::parse("name = \"x\"\n[section]\nfoo = 1\n", LayerFormat::Toml).unwrap();
+
+        layer
+            .set(&["section", "bar"], toml_edit::value("y"))
+            .unwrap();
+        assert_eq!(layer.keys(&["section"]).unwrap(), vec!["foo", "bar"]);
+        assert_eq!(layer.delete(&["name"]).unwrap().as_str(), Some("x"));
+        assert_eq!(
+            layer.to_layer_string().unwrap(),
+            "[section]\nfoo = 1\nbar = \"y\"\n"
+        );
+    }
+}
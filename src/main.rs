@@ -1,66 +1,288 @@
 use std::error::Error;
-use std::io;
-
-use clap::Parser;
-use ratatui::{
-    crossterm::{
-        event::{DisableMouseCapture, EnableMouseCapture},
-        execute,
-        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    },
-    prelude::CrosstermBackend,
-    Terminal,
-};
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, Subcommand};
 
 use color_eyre::Result;
+use ratatui::{crossterm::event::KeyCode, style::Color};
+use tracing::Level;
 
 mod app;
 mod component;
+mod config;
+mod header;
+mod layer;
 mod model;
+mod tui;
 mod ui;
 
-use app::App;
+use app::{App, AppBuilder, AppState};
+use config::{KeyBindings, Theme};
+use header::Header;
+use layer::LayerFormat;
+use model::{ColumnId, ExportFormat, MergeStrategy, Model};
+use tui::{init, register_signal_handlers, restore, Tui};
 use ui::view;
 
 #[derive(Parser)]
+#[command(
+    version,
+    author,
+    about = "Browse and annotate SQLite TCP proto captures",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    sql_path: std::path::PathBuf,
-    layer_path: std::path::PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    sql_path: Option<std::path::PathBuf>,
+    #[arg(required = true)]
+    layer_path: Option<std::path::PathBuf>,
+    /// Format of the layer file (`toml`, `json` or `yaml`), deduced from its extension by default
+    #[arg(long)]
+    format: Option<LayerFormat>,
+    /// Number of rows skipped by PageUp / PageDown
+    #[arg(long, default_value_t = config::DEFAULT_PAGE_SIZE)]
+    page_size: usize,
+    /// Interval between two ticks of the event loop, in milliseconds
+    #[arg(long, default_value_t = config::DEFAULT_TICK_RATE)]
+    tick_rate: u64,
+    /// Most frames drawn per second
+    #[arg(long, default_value_t = config::DEFAULT_FPS, value_parser = clap::value_parser!(u32).range(1..))]
+    fps: u32,
+    /// Do not capture mouse events (for terminal multiplexers mishandling them)
+    #[arg(long)]
+    no_mouse: bool,
+    /// Allow writing to the database (annotations, tags...). Do not use on a database
+    /// still being captured into unless it is in WAL mode
+    #[arg(long)]
+    read_write: bool,
+    /// Create the layer file, without any key, if it does not exist
+    #[arg(long)]
+    create_layer: bool,
+    /// Number of protos whose data is kept in memory once selected
+    #[arg(long, default_value_t = model::DATA_CACHE_SIZE)]
+    cache_size: std::num::NonZeroUsize,
+    /// Score a proto name has to beat to pass the filter in fuzzy mode (Ctrl+F)
+    #[arg(long, default_value_t = config::DEFAULT_FUZZY_THRESHOLD)]
+    fuzzy_threshold: i64,
+    /// Color of the focused texts: a name (`green`), an index (`10`) or `#rrggbb`
+    #[arg(long, value_parser = Color::from_str)]
+    accent_color: Option<Color>,
+    /// Color of the selected row, same syntax as `--accent-color`
+    #[arg(long, value_parser = Color::from_str)]
+    highlight_color: Option<Color>,
+    /// Key opening the quit prompt, `q` by default. Like the other key options,
+    /// it takes over the action bound to that key
+    #[arg(long)]
+    quit_key: Option<char>,
+    /// Key focusing the filter, `f` by default
+    #[arg(long)]
+    filter_key: Option<char>,
+    /// Key opening the layer key editor, `e` by default
+    #[arg(long)]
+    edit_key: Option<char>,
+    /// Key reloading the protos from the database, `r` by default
+    #[arg(long)]
+    refresh_key: Option<char>,
+    /// Where the debug log is written, `debug.log` in the data directory by default
+    #[arg(long, global = true)]
+    log_file: Option<std::path::PathBuf>,
+    /// Log more: `-v` for screen changes, `-vv` for key events, `-vvv` for everything
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Merge two layers without opening the interface
+    Merge {
+        base: std::path::PathBuf,
+        other: std::path::PathBuf,
+        /// Value kept when a key differs: `ours` (base) or `theirs` (other)
+        #[arg(long, default_value = "theirs")]
+        strategy: MergeStrategy,
+        /// Where the merged layer is written
+        #[arg(long)]
+        output: std::path::PathBuf,
+    },
+    /// Write a layer as pretty-printed JSON, for tools not reading TOML
+    ExportJson {
+        layer: std::path::PathBuf,
+        #[arg(long)]
+        output: std::path::PathBuf,
+    },
+    /// Add the keys of a JSON file missing from a layer, existing keys being kept
+    ImportJson {
+        layer: std::path::PathBuf,
+        json: std::path::PathBuf,
+        /// Where the layer is written, the layer itself by default
+        #[arg(long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Export the protos matching a filter without opening the interface
+    Export {
+        sql_path: std::path::PathBuf,
+        layer_path: std::path::PathBuf,
+        /// Same syntax as the filter of the interface, `#tag` included
+        #[arg(long, default_value = "")]
+        filter: String,
+        /// Only export the protos of this session
+        #[arg(long)]
+        session: Option<usize>,
+        /// Comma separated columns among rowid, session, name, timestamp, size and data
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<ColumnId>,
+        /// `csv` or `json`
+        #[arg(long, default_value = "csv")]
+        format: ExportFormat,
+        #[arg(long)]
+        output: std::path::PathBuf,
+    },
+    /// Check a layer against a JSON Schema, exiting with 1 if it is invalid and 2 if
+    /// it cannot be read
+    Validate {
+        layer: std::path::PathBuf,
+        /// JSON Schema file, only the bookmarks are checked without it
+        #[arg(long)]
+        schema: Option<std::path::PathBuf>,
+    },
 }
 
-type Tui = Terminal<CrosstermBackend<io::Stdout>>;
+fn run_command(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Merge {
+            base,
+            other,
+            strategy,
+            output,
+        } => {
+            if strategy == MergeStrategy::Interactive {
+                return Err("interactive merges are only available from the interface".into());
+            }
+            let mut model = Model::without_database(&base)?;
+            model.merge_layer(&other, strategy)?;
+            model.layer_path = output;
+            model.save_layer()?;
+        }
+        Command::ExportJson { layer, output } => {
+            Model::without_database(&layer)?.export_layer_json(&output)?;
+        }
+        Command::ImportJson {
+            layer,
+            json,
+            output,
+        } => {
+            let mut model = Model::without_database(&layer)?;
+            model.import_layer_json(&json)?;
+            if let Some(output) = output {
+                model.layer_path = output;
+            }
+            model.save_layer()?;
+        }
+        Command::Export {
+            sql_path,
+            layer_path,
+            filter,
+            session,
+            mut columns,
+            format,
+            output,
+        } => {
+            let mut model = Model::new(
+                &sql_path,
+                layer_path.clone(),
+                LayerFormat::from_path(&layer_path),
+                false,
+            )?;
+            let mut state = AppState::new(&mut model)?;
+            state.filter(&filter)?;
+            let headers: Vec<&Header> = state
+                .get_filtered_data()?
+                .into_iter()
+                .filter(|header| session.is_none() || header.session_id == session)
+                .collect();
+            if columns.is_empty() {
+                columns = ColumnId::ALL.to_vec();
+            }
+            let written = match format {
+                ExportFormat::Csv => model.export_filtered_csv(&headers, &columns, &output)?,
+                ExportFormat::Json => model.export_filtered_json(&headers, &columns, &output)?,
+            };
+            println!("Exported {written} rows to {}", output.display());
+        }
+        Command::Validate { layer, schema } => {
+            let errors = match Model::without_database(&layer)
+                .and_then(|model| model.validate_layer(schema.as_deref()))
+            {
+                Ok(errors) => errors,
+                Err(err) => {
+                    eprintln!("{err:#}");
+                    std::process::exit(2);
+                }
+            };
+            for error in &errors {
+                println!("{}: {}", error.path, error.message);
+            }
+            if !errors.is_empty() {
+                std::process::exit(1);
+            }
+            println!("{} is valid", layer.display());
+        }
+    }
+    Ok(())
+}
 
-fn run_app(terminal: &mut Tui, app: &mut App) -> Result<(), Box<dyn Error>> {
+fn run_app(
+    terminal: &mut Tui,
+    app: &mut App,
+    terminate: &AtomicBool,
+) -> Result<(), Box<dyn Error>> {
+    let frame_duration = Duration::from_millis(1000 / u64::from(app.fps.max(1)));
     loop {
+        if terminate.load(Ordering::Relaxed) {
+            restore()?;
+            return Ok(());
+        }
+
+        let next_frame = Instant::now() + frame_duration;
         terminal.draw(|f| {
             view(f, &mut app.state, &app.input_arena).expect("View should always work")
         })?;
 
-        app.handle_events()?;
+        app.handle_events(next_frame)?;
         if app.exit {
             return Ok(());
         }
     }
 }
 
-fn init() -> io::Result<Tui> {
-    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
-    enable_raw_mode()?;
-    set_panic_hook();
-    Terminal::new(CrosstermBackend::new(io::stdout()))
-}
-
-fn set_panic_hook() {
-    let hook = std::panic::take_hook();
-    std::panic::set_hook(Box::new(move |panic_info| {
-        ratatui::restore();
-        hook(panic_info);
-    }));
-}
-
-fn restore() -> io::Result<()> {
-    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
-    disable_raw_mode()?;
+// logs go to a file, anything written to stdout or stderr would corrupt the interface
+fn init_logging(path: Option<PathBuf>, verbose: u8) -> Result<(), Box<dyn Error>> {
+    let Some(path) = path.or_else(|| app::data_dir().map(|dir| dir.join("debug.log"))) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    let level = match verbose {
+        0 => Level::ERROR,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_max_level(level)
+        .with_ansi(false)
+        .init();
     Ok(())
 }
 
@@ -68,19 +290,58 @@ fn main() -> Result<(), Box<dyn Error>> {
     color_eyre::install()?;
 
     let args: Cli = Cli::parse();
-    let mut terminal = init()?;
+    init_logging(args.log_file, args.verbose)?;
+    if let Some(command) = args.command {
+        return run_command(command);
+    }
+    let terminate = register_signal_handlers()?;
 
-    // create app and run it
-    let mut app = App::new(args.sql_path, args.layer_path)?;
-    run_app(&mut terminal, &mut app)?;
+    // create app before touching the terminal so setup errors are printed normally
+    let theme = Theme::default();
+    let keys = KeyBindings::default();
+    let key = |key: Option<char>, default| key.map_or(default, KeyCode::Char);
+    let mut app = AppBuilder::default()
+        .db_path(args.sql_path.expect("required without subcommand"))
+        .layer_path(args.layer_path.expect("required without subcommand"))
+        .layer_format(args.format)
+        .page_size(args.page_size)
+        .tick_rate(args.tick_rate)
+        .fps(args.fps)
+        .mouse(!args.no_mouse)
+        .read_write(args.read_write)
+        .create_layer(args.create_layer)
+        .cache_size(args.cache_size)
+        .fuzzy_threshold(args.fuzzy_threshold)
+        .theme(Theme {
+            accent: args.accent_color.unwrap_or(theme.accent),
+            highlight: args.highlight_color.unwrap_or(theme.highlight),
+        })
+        .keybindings(KeyBindings {
+            quit: key(args.quit_key, keys.quit),
+            filter: key(args.filter_key, keys.filter),
+            edit: key(args.edit_key, keys.edit),
+            refresh: key(args.refresh_key, keys.refresh),
+        })
+        .build()?;
 
-    // restore terminal
+    let mut terminal = init(!args.no_mouse)?;
+    let result = run_app(&mut terminal, &mut app, &terminate);
+    let ui_state_saved = app.save_ui_state();
+
+    // restore terminal, whatever the outcome of the app
     if let Err(err) = restore() {
         eprintln!(
             "failed to restore terminal. Run `reset` or restart your terminal to recover: {err}"
         );
     };
     terminal.show_cursor()?;
+    if let Err(err) = ui_state_saved {
+        tracing::error!("failed to save the list position: {err:#}");
+        eprintln!("failed to save the list position: {err:#}");
+    }
+    if let Err(err) = &result {
+        tracing::error!("{err}");
+    }
 
-    Ok(())
+    result
 }
@@ -1,83 +1,2260 @@
-use anyhow::{Context, Result};
-use rusqlite::{Connection, OpenFlags};
+use anyhow::{Context, Error, Result};
+use lru::LruCache;
+use rusqlite::{
+    params_from_iter,
+    types::{Value, ValueRef},
+    Connection, ErrorCode, OpenFlags, OptionalExtension, ToSql,
+};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, DiffTag, TextDiff};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use toml_edit::DocumentMut;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::Duration;
+use toml_edit::{value, Array, Item};
 
-pub struct Header {
-    pub rowid: usize,
-    pub session_id: Option<usize>,
+use crate::header::Header;
+use crate::layer::{LayerAccess, LayerFormat, LayerRepr};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnInfo {
     pub name: String,
-    pub timestamp: String,
+    pub type_affinity: String, // SQLite type affinity derived from the declared type
+}
+
+impl ColumnInfo {
+    // affinity rules from https://www.sqlite.org/datatype3.html#determination_of_column_affinity
+    fn affinity(declared_type: &str) -> &'static str {
+        let declared_type = declared_type.to_uppercase();
+        if declared_type.contains("INT") {
+            "INTEGER"
+        } else if ["CHAR", "CLOB", "TEXT"]
+            .iter()
+            .any(|t| declared_type.contains(t))
+        {
+            "TEXT"
+        } else if declared_type.is_empty() || declared_type.contains("BLOB") {
+            "BLOB"
+        } else if ["REAL", "FLOA", "DOUB"]
+            .iter()
+            .any(|t| declared_type.contains(t))
+        {
+            "REAL"
+        } else {
+            "NUMERIC"
+        }
+    }
+}
+
+// values of a custom query row, by column name
+pub type QueryRow = HashMap<String, String>;
+
+// one line of an annotations CSV file
+#[derive(Deserialize)]
+struct AnnotationRecord {
+    rowid: usize,
+    annotation: String,
+}
+
+// columns that can be exported from the proto list
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnId {
+    RowId,
+    Session,
+    Name,
+    Timestamp,
+    DataSize,
+    Data,
+}
+
+impl ColumnId {
+    pub const ALL: [ColumnId; 6] = [
+        ColumnId::RowId,
+        ColumnId::Session,
+        ColumnId::Name,
+        ColumnId::Timestamp,
+        ColumnId::DataSize,
+        ColumnId::Data,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnId::RowId => "rowid",
+            ColumnId::Session => "session",
+            ColumnId::Name => "name",
+            ColumnId::Timestamp => "timestamp",
+            ColumnId::DataSize => "size",
+            ColumnId::Data => "data",
+        }
+    }
+}
+
+impl FromStr for ColumnId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ColumnId::ALL
+            .into_iter()
+            .find(|column| column.name() == s)
+            .ok_or_else(|| Error::msg(format!("Unknown column `{s}`")))
+    }
+}
+
+// file formats the filtered protos can be exported to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(Error::msg(format!(
+                "Unknown export format `{s}`, expected csv or json"
+            ))),
+        }
+    }
+}
+
+// how `Model::merge_layer` resolves keys having a different value in both layers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeStrategy {
+    Ours,        // keep the existing value
+    Theirs,      // take the value of the other layer
+    Interactive, // let the user pick, conflicts are returned
+}
+
+impl FromStr for MergeStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ours" => Ok(MergeStrategy::Ours),
+            "theirs" => Ok(MergeStrategy::Theirs),
+            "interactive" => Ok(MergeStrategy::Interactive),
+            _ => Err(Error::msg(format!(
+                "Unknown strategy `{s}`, expected ours, theirs or interactive"
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum SortKey {
+    #[default]
+    RowId, // capture order
+    Name,
+    Timestamp,
+    SessionId,
+    DataSize,
+}
+
+impl SortKey {
+    pub fn next(self) -> Self {
+        match self {
+            SortKey::RowId => SortKey::Name,
+            SortKey::Name => SortKey::Timestamp,
+            SortKey::Timestamp => SortKey::SessionId,
+            SortKey::SessionId => SortKey::DataSize,
+            SortKey::DataSize => SortKey::RowId,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SortConfig {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl SortConfig {
+    // ties being broken by rowid in the same direction, as a stable sort then
+    // reversed would
+    fn order_by(&self) -> String {
+        let column = match self.key {
+            SortKey::RowId => "m.rowid",
+            SortKey::Name => "m.proto",
+            SortKey::Timestamp => "m.timestamp",
+            SortKey::SessionId => "m.session",
+            // as `Header::data_size` counts them
+            SortKey::DataSize => "LENGTH(CAST(m.data AS BLOB))",
+        };
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        format!("ORDER BY {column} {direction}, m.rowid {direction}")
+    }
+}
+
+// conditions evaluated by SQLite rather than on the loaded headers, all of them
+// having to hold. Parsed from `name=<LIKE pattern> session=<id> from=<ts> to=<ts>`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlFilter {
+    pub name_like: Option<String>, // `%` and `_` being the LIKE wildcards
+    pub session_eq: Option<usize>,
+    pub timestamp_from: Option<String>, // inclusive, compared as text
+    pub timestamp_to: Option<String>,   // inclusive, compared as text
+}
+
+impl SqlFilter {
+    pub fn is_active(&self) -> bool {
+        *self != SqlFilter::default()
+    }
+
+    // `AND` conditions numbered from `?{first}`, with their parameters
+    fn conditions(&self, first: usize) -> (String, Vec<Value>) {
+        let mut sql = String::new();
+        let mut params = Vec::new();
+        let mut push = |condition: &str, param: Value| {
+            sql.push_str(&format!(" AND {condition} ?{}", first + params.len()));
+            params.push(param);
+        };
+        if let Some(name) = &self.name_like {
+            push("m.proto LIKE", Value::Text(name.clone()));
+        }
+        if let Some(session) = self.session_eq {
+            push("m.session =", Value::Integer(session as i64));
+        }
+        if let Some(from) = &self.timestamp_from {
+            push("m.timestamp >=", Value::Text(from.clone()));
+        }
+        if let Some(to) = &self.timestamp_to {
+            push("m.timestamp <=", Value::Text(to.clone()));
+        }
+        (sql, params)
+    }
+}
+
+impl FromStr for SqlFilter {
+    type Err = Error;
+
+    // words without `=` belong to the previous value, for timestamps such as
+    // `from=2024-01-01 00:00:00`
+    fn from_str(s: &str) -> Result<Self> {
+        let mut pairs: Vec<(&str, String)> = Vec::new();
+        for word in s.split_whitespace() {
+            match (word.split_once('='), pairs.last_mut()) {
+                (Some((key, value)), _) => pairs.push((key, value.to_owned())),
+                (None, Some((_, value))) => {
+                    value.push(' ');
+                    value.push_str(word);
+                }
+                (None, None) => {
+                    return Err(Error::msg(format!("Expected key=value, got `{word}`")))
+                }
+            }
+        }
+
+        let mut filter = SqlFilter::default();
+        for (key, value) in pairs {
+            match key {
+                "name" => filter.name_like = Some(value),
+                "session" => {
+                    filter.session_eq = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("`{value}` is not a session id"))?,
+                    )
+                }
+                "from" => filter.timestamp_from = Some(value),
+                "to" => filter.timestamp_to = Some(value),
+                _ => {
+                    return Err(Error::msg(format!(
+                        "Unknown condition `{key}`, expected name, session, from or to"
+                    )))
+                }
+            }
+        }
+        Ok(filter)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MergeConflict {
+    pub key: String,
+    pub ours: String, // inline representation of the existing value
+    pub theirs: Item,
+}
+
+// a layer value breaking the schema, `path` being a JSON pointer to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffLine {
+    Unchanged(String),
+    Changed(String), // replaced by the line on the other side
+    Added(String),
+    Removed(String),
+}
+
+pub struct LayerEntry {
+    pub key: String,
+    pub is_table: bool,
+    pub value: String, // inline representation of the value, empty for tables
+}
+
+impl LayerEntry {
+    pub fn from(key: &str, item: &Item) -> Self {
+        LayerEntry {
+            key: key.to_owned(),
+            is_table: item.is_table_like(),
+            value: if item.is_table_like() {
+                String::new()
+            } else {
+                item.to_string().trim().to_owned()
+            },
+        }
+    }
+}
+
+const DB_OPEN_FLAGS: OpenFlags = OpenFlags::SQLITE_OPEN_READ_ONLY
+    .union(OpenFlags::SQLITE_OPEN_URI)
+    .union(OpenFlags::SQLITE_OPEN_NO_MUTEX);
+const DB_WRITE_FLAGS: OpenFlags = OpenFlags::SQLITE_OPEN_READ_WRITE
+    .union(OpenFlags::SQLITE_OPEN_CREATE)
+    .union(OpenFlags::SQLITE_OPEN_URI)
+    .union(OpenFlags::SQLITE_OPEN_NO_MUTEX);
+const PAGE_SIZE: usize = 500;
+pub const DATA_CACHE_SIZE: NonZeroUsize = NonZeroUsize::new(50).unwrap(); // protos
+const CREATE_TAGS_TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS tcp_proto_tags (rowid INTEGER, tag TEXT, PRIMARY KEY (rowid, tag))";
+const CREATE_ANNOTATIONS_TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS tcp_proto_annotations (rowid INTEGER PRIMARY KEY, annotation TEXT)";
+// checked when no schema is given: bookmarks are the only key the tool relies on
+const DEFAULT_LAYER_SCHEMA: &str = r#"{
+    "type": "object",
+    "properties": {
+        "bookmarks": {"type": "array", "items": {"type": "integer", "minimum": 0}}
+    }
+}"#;
+const BUSY_TIMEOUT_MS: i64 = 5000;
+const BUSY_RETRIES: usize = 3;
+const BUSY_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+fn is_busy(err: &Error) -> bool {
+    matches!(
+        err.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::DatabaseBusy
+    )
+}
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool> {
+    let mut stmt =
+        conn.prepare_cached("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?")?;
+    Ok(stmt.exists([name])?)
+}
+
+// lazily walks tcp_proto_messages one page at a time, ordered by rowid
+pub struct HeaderIter<'a> {
+    conn: &'a Connection,
+    sql: String,
+    params: Vec<Value>, // of the `SqlFilter` conditions, after the cursor and page size
+    page: std::vec::IntoIter<Header>,
+    last_rowid: usize, // keyset pagination cursor
+    exhausted: bool,
+}
+
+// the columns `Header::from_row` reads, from `tcp_proto_messages m`
+fn select_headers(with_annotations: bool, with_tags: bool) -> String {
+    // the annotations and tags tables only exist once something has been written
+    let (annotation, join) = if with_annotations {
+        (
+            "a.annotation",
+            "LEFT JOIN tcp_proto_annotations a ON a.rowid = m.rowid",
+        )
+    } else {
+        ("NULL", "")
+    };
+    let tags = if with_tags {
+        "(SELECT GROUP_CONCAT(t.tag, ',') FROM tcp_proto_tags t WHERE t.rowid = m.rowid)"
+    } else {
+        "NULL"
+    };
+    format!(
+        "SELECT m.rowid, m.session, m.proto, m.timestamp, {annotation}, {tags},
+                LENGTH(CAST(m.data AS BLOB))
+         FROM tcp_proto_messages m {join}"
+    )
+}
+
+// all the headers in a single query, the rowid cursor of `HeaderIter` not working
+// on another order. Unreadable rows are skipped as `HeaderIter` does
+fn query_sorted_headers(
+    conn: &Connection,
+    with_annotations: bool,
+    with_tags: bool,
+    filter: &SqlFilter,
+    sort: &SortConfig,
+) -> Result<Vec<Header>> {
+    let (conditions, params) = filter.conditions(1);
+    let sql = format!(
+        "{} WHERE 1 = 1{conditions} {}",
+        select_headers(with_annotations, with_tags),
+        sort.order_by()
+    );
+    let mut stmt = conn
+        .prepare_cached(&sql)
+        .context("Failed to query tcp_proto_messages")?;
+    let rows = stmt.query_map(params_from_iter(&params), |row| {
+        Ok((row.get::<_, usize>(0)?, Header::from_row(row)))
+    })?;
+
+    let mut headers = Vec::new();
+    for row in rows {
+        let (rowid, header) = row?;
+        match header {
+            Ok(header) => headers.push(header),
+            Err(err) => tracing::warn!("Cannot read proto at rowid {rowid}: {err}"),
+        }
+    }
+    Ok(headers)
+}
+
+impl<'a> HeaderIter<'a> {
+    fn new(
+        conn: &'a Connection,
+        with_annotations: bool,
+        with_tags: bool,
+        filter: &SqlFilter,
+    ) -> Result<Self> {
+        let (conditions, params) = filter.conditions(3);
+        let sql = format!(
+            "{} WHERE m.rowid > ?1{conditions} ORDER BY m.rowid LIMIT ?2",
+            select_headers(with_annotations, with_tags)
+        );
+        let mut iter = HeaderIter {
+            conn,
+            sql,
+            params,
+            page: Vec::new().into_iter(),
+            last_rowid: 0,
+            exhausted: false,
+        };
+        iter.fetch_page()?;
+        Ok(iter)
+    }
+
+    // rows not fitting a `Header` (a NULL proto name...) are logged and skipped, the
+    // other ones being still listed
+    fn fetch_page(&mut self) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&self.sql)
+            .context("Failed to query tcp_proto_messages")?;
+        let page_params = [
+            Value::Integer(self.last_rowid as i64),
+            Value::Integer(PAGE_SIZE as i64),
+        ];
+        let params = params_from_iter(page_params.iter().chain(&self.params));
+        let rows = stmt.query_map(params, |row| {
+            Ok((row.get::<_, usize>(0)?, Header::from_row(row)))
+        })?;
+
+        let mut page = Vec::with_capacity(PAGE_SIZE);
+        let mut fetched = 0;
+        for row in rows {
+            let (rowid, header) = row?;
+            fetched += 1;
+            self.last_rowid = rowid;
+            match header {
+                Ok(header) => page.push(header),
+                Err(err) => tracing::warn!("Cannot read proto at rowid {rowid}: {err}"),
+            }
+        }
+
+        self.exhausted = fetched < PAGE_SIZE;
+        self.page = page.into_iter();
+        Ok(())
+    }
 }
 
-impl Header {
-    pub fn from(rowid: usize, session_id: Option<usize>, name: String, timestamp: String) -> Self {
-        Header {
-            rowid,
-            session_id,
-            name,
-            timestamp,
+impl Iterator for HeaderIter<'_> {
+    type Item = Result<Header>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a page may only hold skipped rows
+        loop {
+            if let Some(header) = self.page.next() {
+                return Some(Ok(header));
+            }
+            if self.exhausted {
+                return None;
+            }
+            if let Err(err) = self.fetch_page() {
+                self.exhausted = true;
+                return Some(Err(err));
+            }
         }
     }
 }
 
+// `conn` is read-only unless opened with `read_write`, write operations fail otherwise.
+// It is opened with SQLITE_OPEN_NO_MUTEX, which only holds as long as the `Model`
+// stays on a single thread: background queries open a connection of their own, and
+// sharing `conn` across threads would require dropping that flag. The statements run
+// on each selection or page are kept in the statement cache of `conn`
 pub struct Model {
-    pub conn: Connection,               // sqlite connection having all data needed
-    pub layer: DocumentMut,             // layer datas
-    pub layer_path: std::path::PathBuf, // path of the file for saving it - Placeholder
+    pub conn: Connection,                 // sqlite connection having all data needed
+    pub db_path: std::path::PathBuf,      // path of the database, used to reconnect
+    pub flags: OpenFlags,                 // flags the connection has been opened with
+    pub layer: LayerRepr,                 // layer datas, in the format of the file
+    pub layer_path: std::path::PathBuf,   // path of the file for saving it - Placeholder
+    data_cache: LruCache<usize, Vec<u8>>, // data of the last selected protos, by rowid
+    cache_hits: usize,
+    cache_misses: usize,
 }
 
 impl Model {
-    pub fn new(db_path: &std::path::PathBuf, layer_path: std::path::PathBuf) -> Result<Self> {
-        let conn = Connection::open_with_flags(
-            db_path,
-            OpenFlags::SQLITE_OPEN_READ_ONLY
-                | OpenFlags::SQLITE_OPEN_URI
-                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
-        )
-        .with_context(|| format!("Failing to connect to `{:?}`", &db_path))?;
+    pub fn new(
+        db_path: &Path,
+        layer_path: PathBuf,
+        layer_format: LayerFormat,
+        read_write: bool,
+    ) -> Result<Self> {
+        let flags = if read_write {
+            DB_WRITE_FLAGS
+        } else {
+            DB_OPEN_FLAGS
+        };
+        let conn = Connection::open_with_flags(db_path, flags)
+            .with_context(|| format!("Failing to connect to `{:?}`", &db_path))?;
+
+        if !layer_path.exists() {
+            return Err(Error::msg(format!(
+                "Layer file '{}' not found. Use --create-layer to create it.",
+                layer_path.display()
+            )));
+        }
+        let layer = Model::read_layer(&layer_path, layer_format)?;
+
+        let model = Model::from_connection(conn, layer, layer_path);
+        model.enable_wal_mode()?;
+        Ok(model)
+    }
+
+    // make concurrent access with the capturing tool more resilient
+    pub fn enable_wal_mode(&self) -> Result<()> {
+        self.conn
+            .pragma_update(None, "busy_timeout", BUSY_TIMEOUT_MS)
+            .context("Unable to set busy_timeout")?;
+        self.conn
+            .pragma_update(None, "synchronous", "NORMAL")
+            .context("Unable to set synchronous mode")?;
+
+        // switching the journal mode is a write: read-only connections rely on
+        // the writer having enabled it, reading a WAL database is always fine
+        if !self.flags.contains(OpenFlags::SQLITE_OPEN_READ_ONLY) {
+            self.conn
+                .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+                .context("Unable to enable WAL mode")?;
+        }
 
-        let contents = fs::read_to_string(&layer_path)
+        Ok(())
+    }
+
+    // writes a layer without any key, for `--create-layer`
+    pub fn create_layer(layer_path: &Path, format: LayerFormat) -> Result<()> {
+        fs::write(layer_path, LayerRepr::empty(format).to_layer_string()?)
+            .with_context(|| format!("Could not write file `{:?}`", &layer_path))
+    }
+
+    fn read_layer(layer_path: &std::path::Path, format: LayerFormat) -> Result<LayerRepr> {
+        let contents = fs::read_to_string(layer_path)
             .with_context(|| format!("Could not read file `{:?}`", &layer_path))?;
 
-        let layer: DocumentMut = contents
-            .parse::<DocumentMut>()
-            .with_context(|| format!("Unable to parse TOML from `{:?}`", &layer_path))?;
+        LayerRepr::parse(&contents, format)
+            .with_context(|| format!("Invalid layer `{:?}`", &layer_path))
+    }
+
+    // layer-only model for the subcommands never reading protos, the database is
+    // an empty in-memory one, the format is deduced from the extension of the file
+    pub fn without_database(layer_path: &Path) -> Result<Self> {
+        let layer = Model::read_layer(layer_path, LayerFormat::from_path(layer_path))?;
+        Ok(Model::from_connection(
+            Connection::open_in_memory()?,
+            layer,
+            layer_path.to_path_buf(),
+        ))
+    }
 
-        Ok(Model {
+    pub fn reload_layer(&mut self) -> Result<()> {
+        // keep the format the layer has been opened with, even if forced
+        self.layer = Model::read_layer(&self.layer_path, self.layer.format())?;
+        Ok(())
+    }
+
+    pub fn from_connection(
+        conn: Connection,
+        layer: LayerRepr,
+        layer_path: std::path::PathBuf,
+    ) -> Self {
+        Model {
+            db_path: conn
+                .path()
+                .map(std::path::PathBuf::from)
+                .unwrap_or_default(),
+            flags: if conn.is_readonly("main").unwrap_or(true) {
+                DB_OPEN_FLAGS
+            } else {
+                DB_WRITE_FLAGS
+            },
             conn,
             layer,
             layer_path,
+            data_cache: LruCache::new(DATA_CACHE_SIZE),
+            cache_hits: 0,
+            cache_misses: 0,
+        }
+    }
+
+    pub fn set_cache_size(&mut self, size: NonZeroUsize) {
+        self.data_cache.resize(size);
+    }
+
+    // hits and misses of the data cache of `query_data_bytes`
+    pub fn cache_stats(&self) -> (usize, usize) {
+        (self.cache_hits, self.cache_misses)
+    }
+
+    pub fn reconnect(&mut self) -> Result<()> {
+        // the previous connection is closed when replaced
+        self.conn = Connection::open_with_flags(&self.db_path, self.flags)
+            .with_context(|| format!("Failing to reconnect to `{:?}`", &self.db_path))?;
+        self.enable_wal_mode()
+    }
+
+    // reads the database header only, cheap enough to run before each refresh
+    pub fn is_connected(&self) -> bool {
+        self.conn
+            .query_row("PRAGMA schema_version", [], |row| row.get::<_, i64>(0))
+            .is_ok()
+    }
+
+    // run the query again on a fresh connection while the database is busy
+    fn with_retry<T>(&mut self, query: impl Fn(&Model) -> Result<T>) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match query(self) {
+                Err(err) if attempt < BUSY_RETRIES && is_busy(&err) => {
+                    attempt += 1;
+                    std::thread::sleep(BUSY_RETRY_DELAY);
+                    self.reconnect()?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub fn is_writable(&self) -> bool {
+        !self.flags.contains(OpenFlags::SQLITE_OPEN_READ_ONLY)
+    }
+
+    // runs `write` on `conn`, refusing when the database was opened read-only
+    fn with_writer<T>(&self, write: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        if !self.is_writable() {
+            return Err(Error::msg("Database opened read-only"));
+        }
+        write(&self.conn)
+    }
+
+    fn has_table(&self, name: &str) -> Result<bool> {
+        table_exists(&self.conn, name)
+    }
+
+    pub fn list_tables(&mut self) -> Result<Vec<String>> {
+        self.with_retry(|model| {
+            let mut stmt = model
+                .conn
+                .prepare("SELECT name FROM sqlite_master WHERE type='table' ORDER BY name")?;
+            let tables = stmt.query_map([], |row| row.get(0))?;
+            Ok(tables.collect::<rusqlite::Result<_>>()?)
         })
     }
 
-    pub fn query_protos(&self) -> Result<Vec<Header>> {
-        let mut stmt = self
+    pub fn list_columns(&mut self, table: &str) -> Result<Vec<ColumnInfo>> {
+        self.with_retry(|model| {
+            let mut stmt = model
+                .conn
+                .prepare("SELECT name, type FROM pragma_table_info(?)")?;
+            let columns = stmt.query_map([table], |row| {
+                Ok(ColumnInfo {
+                    name: row.get(0)?,
+                    type_affinity: ColumnInfo::affinity(&row.get::<_, String>(1)?).to_owned(),
+                })
+            })?;
+            columns
+                .collect::<rusqlite::Result<_>>()
+                .with_context(|| format!("Cannot read the columns of `{table}`"))
+        })
+    }
+
+    pub fn custom_query_columns(&self, sql: &str) -> Result<Vec<String>> {
+        let stmt = self.conn.prepare(sql)?;
+        Ok(stmt.column_names().into_iter().map(str::to_owned).collect())
+    }
+
+    // runs any query, every value being rendered as text; `conn` being read-only,
+    // statements modifying the database are rejected by SQLite
+    pub fn execute_custom_query(&mut self, sql: &str) -> Result<Vec<QueryRow>> {
+        self.with_retry(|model| {
+            let mut stmt = model.conn.prepare(sql)?;
+            let mut rows = stmt.query([])?;
+
+            let mut results = Vec::new();
+            while let Some(row) = rows.next()? {
+                let mut result = HashMap::new();
+                for (i, name) in row.as_ref().column_names().into_iter().enumerate() {
+                    let value = match row.get_ref(i)? {
+                        ValueRef::Null => String::from("NULL"),
+                        ValueRef::Integer(v) => v.to_string(),
+                        ValueRef::Real(v) => v.to_string(),
+                        ValueRef::Text(v) => String::from_utf8_lossy(v).into_owned(),
+                        ValueRef::Blob(v) => format!("<{} bytes>", v.len()),
+                    };
+                    result.insert(name.to_owned(), value);
+                }
+                results.push(result);
+            }
+            Ok(results)
+        })
+    }
+
+    pub fn query_protos_iter(&self) -> Result<impl Iterator<Item = Result<Header>> + '_> {
+        self.query_protos_filtered_iter(&SqlFilter::default())
+    }
+
+    fn query_protos_filtered_iter(
+        &self,
+        filter: &SqlFilter,
+    ) -> Result<impl Iterator<Item = Result<Header>> + '_> {
+        HeaderIter::new(
+            &self.conn,
+            self.has_table("tcp_proto_annotations")?,
+            self.has_table("tcp_proto_tags")?,
+            filter,
+        )
+    }
+
+    pub fn query_protos(&mut self) -> Result<Vec<Header>> {
+        self.with_retry(|model| model.query_protos_iter()?.collect())
+    }
+
+    pub fn query_protos_filtered(&mut self, filter: &SqlFilter) -> Result<Vec<Header>> {
+        self.with_retry(|model| model.query_protos_filtered_iter(filter)?.collect())
+    }
+
+    // sorted by SQLite rather than once loaded
+    pub fn query_protos_sorted(
+        &mut self,
+        filter: &SqlFilter,
+        sort: &SortConfig,
+    ) -> Result<Vec<Header>> {
+        self.with_retry(|model| {
+            query_sorted_headers(
+                &model.conn,
+                model.has_table("tcp_proto_annotations")?,
+                model.has_table("tcp_proto_tags")?,
+                filter,
+                sort,
+            )
+        })
+    }
+
+    // same as `query_protos_filtered`, or `query_protos_sorted` given a sort, on a
+    // connection of its own, the headers being sent once all of them are read.
+    // In-memory databases cannot be opened twice
+    pub fn query_protos_in_background(
+        &self,
+        filter: SqlFilter,
+        sort: Option<SortConfig>,
+    ) -> Option<mpsc::Receiver<Result<Vec<Header>>>> {
+        if self.conn.path().is_none_or(str::is_empty) {
+            return None;
+        }
+        let db_path = self.db_path.clone();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let headers = Connection::open_with_flags(&db_path, DB_OPEN_FLAGS)
+                .with_context(|| format!("Failing to connect to `{:?}`", &db_path))
+                .and_then(|conn| {
+                    conn.pragma_update(None, "busy_timeout", BUSY_TIMEOUT_MS)?;
+                    let with_annotations = table_exists(&conn, "tcp_proto_annotations")?;
+                    let with_tags = table_exists(&conn, "tcp_proto_tags")?;
+                    match sort {
+                        Some(sort) => {
+                            query_sorted_headers(&conn, with_annotations, with_tags, &filter, &sort)
+                        }
+                        None => {
+                            HeaderIter::new(&conn, with_annotations, with_tags, &filter)?.collect()
+                        }
+                    }
+                });
+            // the receiver is gone when the app quit meanwhile
+            let _ = sender.send(headers);
+        });
+        Some(receiver)
+    }
+
+    // names of the `[views]` of the layer, each one being a SELECT returning at least
+    // the `rowid, session, proto, timestamp` columns
+    pub fn list_views(&self) -> Vec<String> {
+        self.layer.keys(&["views"]).unwrap_or_default()
+    }
+
+    fn view_sql(&self, name: &str) -> Result<String> {
+        self.layer
+            .get(&["views", name])
+            .as_ref()
+            .and_then(Item::as_str)
+            .map(|sql| sql.trim().trim_end_matches(';').to_owned())
+            .with_context(|| format!("Unknown view `{name}`"))
+    }
+
+    // named parameters of a view (`:session`, `@name` or `$name`), asked for before running it
+    pub fn view_parameters(&self, name: &str) -> Result<Vec<String>> {
+        let stmt = self
             .conn
-            .prepare("SELECT rowid, session, proto, timestamp FROM tcp_proto_messages")?;
-        let rows = stmt.query_map([], |row| {
-            Ok(Header::from(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-            ))
+            .prepare(&self.view_sql(name)?)
+            .with_context(|| format!("Cannot run view `{name}`"))?;
+        (1..=stmt.parameter_count())
+            .map(|i| {
+                stmt.parameter_name(i).map(str::to_owned).with_context(|| {
+                    format!("View `{name}` has unnamed parameters, name them like `:session`")
+                })
+            })
+            .collect()
+    }
+
+    // `parameters` binds a value to each parameter of the view, by name
+    pub fn execute_custom_view(
+        &mut self,
+        name: &str,
+        parameters: &[(String, String)],
+    ) -> Result<Vec<Header>> {
+        let sql = self.view_sql(name)?;
+
+        self.with_retry(|model| {
+            let annotation = if model.has_table("tcp_proto_annotations")? {
+                "(SELECT a.annotation FROM tcp_proto_annotations a WHERE a.rowid = v.rowid)"
+            } else {
+                "NULL"
+            };
+            let tags = if model.has_table("tcp_proto_tags")? {
+                "(SELECT GROUP_CONCAT(t.tag, ',') FROM tcp_proto_tags t WHERE t.rowid = v.rowid)"
+            } else {
+                "NULL"
+            };
+            // sub-queries instead of joins so the ordering of the view is kept
+            let mut stmt = model.conn.prepare(&format!(
+                "SELECT v.rowid, v.session, v.proto, v.timestamp, {annotation}, {tags},
+                    (SELECT LENGTH(CAST(d.data AS BLOB)) FROM tcp_proto_messages d
+                     WHERE d.rowid = v.rowid)
+                 FROM ({sql}) v"
+            ))?;
+            let parameters: Vec<(&str, &dyn ToSql)> = parameters
+                .iter()
+                .map(|(name, value)| (name.as_str(), value as &dyn ToSql))
+                .collect();
+            let headers = stmt
+                .query_map(parameters.as_slice(), Header::from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(headers)
+        })
+        .with_context(|| format!("Cannot run view `{name}`"))
+    }
+
+    // works for TEXT and BLOB data alike. Protos are never modified, their data
+    // is kept in the cache until evicted or deleted
+    pub fn query_data_bytes(&mut self, proto_id: &usize) -> Result<Vec<u8>> {
+        if let Some(data) = self.data_cache.get(proto_id) {
+            self.cache_hits += 1;
+            return Ok(data.clone());
+        }
+        let data = self.with_retry(|model| {
+            let mut stmt = model
+                .conn
+                .prepare_cached("SELECT data FROM tcp_proto_messages WHERE rowid = ?")?;
+            Ok(stmt.query_one(&[(1, proto_id)], |row| {
+                Ok(row.get_ref(0)?.as_bytes()?.to_vec())
+            })?)
         })?;
+        self.cache_misses += 1;
+        self.data_cache.put(*proto_id, data.clone());
+        Ok(data)
+    }
 
-        Ok(rows.filter_map(Result::ok).collect::<Vec<Header>>())
+    // data sub-query failures write an empty cell instead of aborting the export
+    // blobs are not always valid UTF-8, invalid sequences are exported as U+FFFD
+    fn export_data(&mut self, proto_id: usize) -> String {
+        match self.query_data_bytes(&proto_id) {
+            Ok(data) => String::from_utf8_lossy(&data).into_owned(),
+            Err(err) => {
+                tracing::warn!("Cannot read the data of rowid {proto_id}: {err:#}");
+                String::new()
+            }
+        }
     }
 
-    pub fn query_data(&self, proto_id: &usize) -> Result<String> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT data FROM tcp_proto_messages WHERE rowid = ?")?;
-        let rows = stmt.query_one(&[(1, proto_id)], |row| Ok(row.get(0)?))?;
+    pub fn export_filtered_csv(
+        &mut self,
+        headers: &[&Header],
+        columns: &[ColumnId],
+        path: &Path,
+    ) -> Result<u64> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(true)
+            .from_path(path)
+            .with_context(|| format!("Could not create file `{}`", path.display()))?;
+        writer.write_record(columns.iter().map(ColumnId::name))?;
+
+        let mut written = 0;
+        for header in headers {
+            let record: Vec<String> = columns
+                .iter()
+                .map(|column| match column {
+                    ColumnId::RowId => header.rowid.to_string(),
+                    ColumnId::Session => header
+                        .session_id
+                        .map(|id| id.to_string())
+                        .unwrap_or_default(),
+                    ColumnId::Name => header.name.clone(),
+                    ColumnId::Timestamp => header.timestamp.clone(),
+                    ColumnId::DataSize => header
+                        .data_size
+                        .map(|size| size.to_string())
+                        .unwrap_or_default(),
+                    ColumnId::Data => self.export_data(header.rowid),
+                })
+                .collect();
+            writer.write_record(&record)?;
+            written += 1;
+        }
+
+        writer
+            .flush()
+            .with_context(|| format!("Could not write file `{}`", path.display()))?;
+        Ok(written)
+    }
 
-        Ok(rows)
+    // same columns as the CSV export, as an array of objects keeping numbers as numbers
+    pub fn export_filtered_json(
+        &mut self,
+        headers: &[&Header],
+        columns: &[ColumnId],
+        path: &Path,
+    ) -> Result<u64> {
+        let records: Vec<serde_json::Value> = headers
+            .iter()
+            .map(|header| {
+                columns
+                    .iter()
+                    .map(|column| {
+                        let value = match column {
+                            ColumnId::RowId => header.rowid.into(),
+                            ColumnId::Session => header.session_id.into(),
+                            ColumnId::Name => header.name.as_str().into(),
+                            ColumnId::Timestamp => header.timestamp.as_str().into(),
+                            ColumnId::DataSize => header.data_size.into(),
+                            ColumnId::Data => self.export_data(header.rowid).into(),
+                        };
+                        (column.name().to_owned(), value)
+                    })
+                    .collect::<serde_json::Map<_, _>>()
+                    .into()
+            })
+            .collect();
+
+        fs::write(path, serde_json::to_string_pretty(&records)? + "\n")
+            .with_context(|| format!("Could not write file `{}`", path.display()))?;
+        Ok(records.len() as u64)
+    }
+
+    // upserts every `rowid,annotation` line of the CSV file, returns the number of lines
+    pub fn import_annotations(&self, path: &Path) -> Result<usize> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_path(path)
+            .with_context(|| format!("Could not read file `{}`", path.display()))?;
+
+        self.with_writer(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(CREATE_ANNOTATIONS_TABLE, [])?;
+
+            let mut processed = 0;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO tcp_proto_annotations (rowid, annotation) VALUES (?1, ?2)
+                     ON CONFLICT(rowid) DO UPDATE SET annotation = excluded.annotation",
+                )?;
+                for record in reader.deserialize() {
+                    let record: AnnotationRecord = record.with_context(|| {
+                        format!("Invalid annotation line in `{}`", path.display())
+                    })?;
+                    stmt.execute((record.rowid, record.annotation))?;
+                    processed += 1;
+                }
+            }
+
+            tx.commit()?;
+            Ok(processed)
+        })
+    }
+
+    pub fn set_annotation(&self, rowid: usize, annotation: &str) -> Result<()> {
+        self.with_writer(|conn| {
+            conn.execute(CREATE_ANNOTATIONS_TABLE, [])?;
+            conn.execute(
+                "INSERT INTO tcp_proto_annotations (rowid, annotation) VALUES (?1, ?2)
+                 ON CONFLICT(rowid) DO UPDATE SET annotation = excluded.annotation",
+                (rowid, annotation),
+            )
+            .with_context(|| format!("Cannot annotate proto at rowid {rowid}"))?;
+            Ok(())
+        })
+    }
+
+    pub fn add_tag(&self, rowid: usize, tag: &str) -> Result<()> {
+        // tags are stored comma separated in headers and filtered on as a single word
+        if tag.is_empty() || tag.contains(|c: char| c == ',' || c.is_whitespace()) {
+            return Err(Error::msg(format!(
+                "`{tag}` is not a valid tag, it must be a single word without commas"
+            )));
+        }
+
+        self.with_writer(|conn| {
+            conn.execute(CREATE_TAGS_TABLE, [])?;
+            conn.execute(
+                "INSERT OR IGNORE INTO tcp_proto_tags (rowid, tag) VALUES (?1, ?2)",
+                (rowid, tag),
+            )
+            .with_context(|| format!("Cannot tag proto at rowid {rowid}"))?;
+            Ok(())
+        })
+    }
+
+    pub fn remove_tag(&self, rowid: usize, tag: &str) -> Result<()> {
+        if !self.has_table("tcp_proto_tags")? {
+            return Ok(());
+        }
+
+        self.with_writer(|conn| {
+            conn.execute(
+                "DELETE FROM tcp_proto_tags WHERE rowid = ?1 AND tag = ?2",
+                (rowid, tag),
+            )
+            .with_context(|| format!("Cannot untag proto at rowid {rowid}"))?;
+            Ok(())
+        })
+    }
+
+    pub fn add_proto(
+        &self,
+        session: Option<usize>,
+        name: &str,
+        timestamp: &str,
+        data: &str,
+    ) -> Result<usize> {
+        self.with_writer(|conn| {
+            conn.execute(
+                "INSERT INTO tcp_proto_messages (session, proto, timestamp, data)
+                 VALUES (?1, ?2, ?3, ?4)",
+                (session, name, timestamp, data),
+            )
+            .with_context(|| format!("Cannot add proto `{name}`"))?;
+            Ok(conn.last_insert_rowid() as usize)
+        })
+    }
+
+    pub fn duplicate_proto(&self, rowid: usize) -> Result<usize> {
+        self.with_writer(|conn| {
+            let copied = conn
+                .execute(
+                    "INSERT INTO tcp_proto_messages (session, proto, timestamp, data)
+                     SELECT session, proto || ' (copy)', timestamp, data
+                     FROM tcp_proto_messages WHERE rowid = ?",
+                    [rowid],
+                )
+                .with_context(|| format!("Cannot duplicate proto at rowid {rowid}"))?;
+            if copied == 0 {
+                return Err(Error::msg(format!("No proto at rowid {rowid}")));
+            }
+            Ok(conn.last_insert_rowid() as usize)
+        })
+    }
+
+    pub fn delete_proto(&mut self, rowid: usize) -> Result<()> {
+        let with_annotations = self.has_table("tcp_proto_annotations")?;
+        let with_tags = self.has_table("tcp_proto_tags")?;
+        // SQLite may give the rowid to the next proto added
+        self.data_cache.pop(&rowid);
+
+        self.with_writer(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute("DELETE FROM tcp_proto_messages WHERE rowid = ?", [rowid])
+                .with_context(|| format!("Cannot delete proto at rowid {rowid}"))?;
+            if with_annotations {
+                tx.execute("DELETE FROM tcp_proto_annotations WHERE rowid = ?", [rowid])?;
+            }
+            if with_tags {
+                tx.execute("DELETE FROM tcp_proto_tags WHERE rowid = ?", [rowid])?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    pub fn query_unique_tags(&mut self) -> Result<Vec<String>> {
+        self.with_retry(|model| {
+            if !model.has_table("tcp_proto_tags")? {
+                return Ok(Vec::new());
+            }
+            let mut stmt = model
+                .conn
+                .prepare("SELECT DISTINCT tag FROM tcp_proto_tags ORDER BY tag")?;
+            let tags = stmt.query_map([], |row| row.get(0))?;
+            Ok(tags.collect::<rusqlite::Result<_>>()?)
+        })
+    }
+
+    // number of protos captured in each session, `None` counting those without one
+    pub fn count_protos_by_session(&mut self) -> Result<HashMap<Option<usize>, usize>> {
+        self.with_retry(|model| {
+            let mut stmt = model
+                .conn
+                .prepare("SELECT session, COUNT(*) FROM tcp_proto_messages GROUP BY session")?;
+            let counts = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            Ok(counts.collect::<rusqlite::Result<_>>()?)
+        })
+    }
+
+    pub fn query_annotation(&mut self, rowid: usize) -> Result<Option<String>> {
+        self.with_retry(|model| {
+            if !model.has_table("tcp_proto_annotations")? {
+                return Ok(None);
+            }
+            let mut stmt = model
+                .conn
+                .prepare_cached("SELECT annotation FROM tcp_proto_annotations WHERE rowid = ?")?;
+            Ok(stmt.query_row([rowid], |row| row.get(0)).optional()?)
+        })
+    }
+
+    pub fn query_layer_entries(&self, path: &[&str]) -> Result<Vec<LayerEntry>> {
+        let mut entries = Vec::new();
+        for key in self.layer.keys(path)? {
+            let item_path: Vec<&str> = path.iter().copied().chain([key.as_str()]).collect();
+            let item = self.layer.get(&item_path).unwrap_or_default();
+            entries.push(LayerEntry::from(&key, &item));
+        }
+        Ok(entries)
+    }
+
+    pub fn layer_as_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(&self.layer.to_json()?)
+            .context("Unable to serialize the layer to JSON")
+    }
+
+    pub fn export_layer_json(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.layer_as_json()?)
+            .with_context(|| format!("Could not write file `{}`", path.display()))
+    }
+
+    // keys already in the layer are kept, whatever the file says
+    pub fn import_layer_json(&mut self, path: &Path) -> Result<()> {
+        let other_layer = Model::read_layer(path, LayerFormat::Json)?;
+        self.merge_layer_repr(&other_layer, MergeStrategy::Ours)?;
+        Ok(())
+    }
+
+    pub fn layer_keys(&self, path: &[&str]) -> Vec<String> {
+        self.layer.keys(path).unwrap_or_default()
+    }
+
+    pub fn set_layer_key(&mut self, key: &str, value: Item) -> Result<()> {
+        self.set_layer_key_at_path(&[key], value)
+    }
+
+    // missing tables along the path are created
+    pub fn set_layer_key_at_path(&mut self, path: &[&str], value: Item) -> Result<()> {
+        if path.is_empty() || path.iter().any(|key| key.is_empty()) {
+            return Err(Error::msg("Key cannot be empty"));
+        }
+        self.layer.set(path, value)
+    }
+
+    pub fn delete_layer_key(&mut self, path: &[&str]) -> Result<()> {
+        self.layer
+            .delete(path)
+            .map(|_| ())
+            .with_context(|| format!("No `{}` key in the layer", path.join(".")))
+    }
+
+    // names shown instead of the database columns, from a `[column_aliases]` table
+    // such as `proto = "Protocol Name"`
+    pub fn column_aliases(&self) -> HashMap<String, String> {
+        self.layer
+            .get(&["column_aliases"])
+            .as_ref()
+            .and_then(Item::as_table_like)
+            .map(|aliases| {
+                aliases
+                    .iter()
+                    .filter_map(|(column, alias)| {
+                        Some((column.to_owned(), alias.as_str()?.to_owned()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // bookmarked rowids, stored as a `bookmarks` integer array in the layer
+    pub fn query_bookmarks(&self) -> HashSet<usize> {
+        self.layer
+            .get(&["bookmarks"])
+            .as_ref()
+            .and_then(Item::as_array)
+            .map(|bookmarks| {
+                bookmarks
+                    .iter()
+                    .filter_map(|rowid| rowid.as_integer())
+                    .filter_map(|rowid| usize::try_from(rowid).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn set_bookmarks(&mut self, bookmarks: &HashSet<usize>) -> Result<()> {
+        let mut rowids: Vec<i64> = bookmarks
+            .iter()
+            .map(|rowid| i64::try_from(*rowid))
+            .collect::<Result<_, _>>()?;
+        rowids.sort();
+        self.set_layer_key("bookmarks", value(Array::from_iter(rowids)))
+    }
+
+    // merges the top-level keys of another layer file, keys only found in the other
+    // layer are always added. Returns the conflicts left for the user to resolve,
+    // which only happens with `MergeStrategy::Interactive`
+    pub fn merge_layer(
+        &mut self,
+        other_path: &Path,
+        strategy: MergeStrategy,
+    ) -> Result<Vec<MergeConflict>> {
+        let other_layer = Model::read_layer(other_path, LayerFormat::from_path(other_path))?;
+        self.merge_layer_repr(&other_layer, strategy)
+    }
+
+    fn merge_layer_repr(
+        &mut self,
+        other_layer: &LayerRepr,
+        strategy: MergeStrategy,
+    ) -> Result<Vec<MergeConflict>> {
+        let mut conflicts = Vec::new();
+        for key in other_layer.keys(&[])? {
+            let Some(theirs) = other_layer.get(&[&key]) else {
+                continue;
+            };
+            let ours = self
+                .layer
+                .get(&[&key])
+                .map(|item| item.to_string().trim().to_owned());
+            match ours {
+                None => self.set_layer_key(&key, theirs)?,
+                Some(ours) if ours == theirs.to_string().trim() => {}
+                Some(ours) => match strategy {
+                    MergeStrategy::Ours => {}
+                    MergeStrategy::Theirs => self.set_layer_key(&key, theirs)?,
+                    MergeStrategy::Interactive => {
+                        conflicts.push(MergeConflict { key, ours, theirs })
+                    }
+                },
+            }
+        }
+
+        Ok(conflicts)
+    }
+
+    // line-level diff of the serialized layers
+    pub fn layer_diff(old: &LayerRepr, new: &LayerRepr) -> Result<Vec<DiffLine>> {
+        let old = old.to_layer_string()?;
+        let new = new.to_layer_string()?;
+
+        Ok(TextDiff::from_lines(&old, &new)
+            .iter_all_changes()
+            .map(|change| {
+                let line = change.value().trim_end_matches(['\r', '\n']).to_owned();
+                match change.tag() {
+                    ChangeTag::Equal => DiffLine::Unchanged(line),
+                    ChangeTag::Delete => DiffLine::Removed(line),
+                    ChangeTag::Insert => DiffLine::Added(line),
+                }
+            })
+            .collect())
+    }
+
+    // side-by-side diff of two proto datas, each row holding the left and the right
+    // line, `None` being used to pad the side the line is missing from
+    pub fn data_diff(left: &str, right: &str) -> Vec<(Option<DiffLine>, Option<DiffLine>)> {
+        let old: Vec<&str> = left.lines().collect();
+        let new: Vec<&str> = right.lines().collect();
+        let line = |lines: &[&str], i: usize, kind: fn(String) -> DiffLine| {
+            lines.get(i).map(|l| kind(l.to_string()))
+        };
+
+        let mut rows = Vec::with_capacity(old.len().max(new.len()));
+        for op in TextDiff::from_lines(left, right).ops() {
+            let (tag, old_range, new_range) = op.as_tag_tuple();
+            match tag {
+                DiffTag::Equal => rows.extend(old_range.zip(new_range).map(|(i, j)| {
+                    (
+                        line(&old, i, DiffLine::Unchanged),
+                        line(&new, j, DiffLine::Unchanged),
+                    )
+                })),
+                DiffTag::Delete => {
+                    rows.extend(old_range.map(|i| (line(&old, i, DiffLine::Removed), None)))
+                }
+                DiffTag::Insert => {
+                    rows.extend(new_range.map(|j| (None, line(&new, j, DiffLine::Added))))
+                }
+                DiffTag::Replace => {
+                    let paired = old_range.len().min(new_range.len());
+                    for k in 0..old_range.len().max(new_range.len()) {
+                        // lines without a counterpart are plain removals / additions
+                        let changed = k < paired;
+                        let removed = if changed {
+                            DiffLine::Changed
+                        } else {
+                            DiffLine::Removed
+                        };
+                        let added = if changed {
+                            DiffLine::Changed
+                        } else {
+                            DiffLine::Added
+                        };
+                        rows.push((
+                            old_range
+                                .clone()
+                                .nth(k)
+                                .and_then(|i| line(&old, i, removed)),
+                            new_range.clone().nth(k).and_then(|j| line(&new, j, added)),
+                        ));
+                    }
+                }
+            }
+        }
+
+        rows
+    }
+
+    // the layer is checked as JSON whatever its format
+    pub fn validate_layer(&self, schema_path: Option<&Path>) -> Result<Vec<ValidationError>> {
+        let schema: serde_json::Value = match schema_path {
+            Some(path) => {
+                let contents = fs::read_to_string(path)
+                    .with_context(|| format!("Could not read file `{:?}`", &path))?;
+                serde_json::from_str(&contents)
+                    .with_context(|| format!("Invalid schema `{:?}`", &path))?
+            }
+            None => serde_json::from_str(DEFAULT_LAYER_SCHEMA)?,
+        };
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|err| Error::msg(format!("Invalid schema: {err}")))?;
+
+        let layer = self.layer.to_json()?;
+        Ok(validator
+            .iter_errors(&layer)
+            .map(|err| ValidationError {
+                path: match err.instance_path().to_string() {
+                    path if path.is_empty() => "/".to_owned(),
+                    path => path,
+                },
+                message: err.to_string(),
+            })
+            .collect())
+    }
+
+    // compares values rather than text, the file may be formatted differently
+    pub fn is_dirty(&self) -> bool {
+        match fs::read_to_string(&self.layer_path) {
+            Ok(saved) => {
+                let saved = LayerRepr::parse(&saved, self.layer.format())
+                    .and_then(|saved| saved.to_json())
+                    .ok();
+                saved.is_none() || self.layer.to_json().ok() != saved
+            }
+            // nothing saved yet, only an empty layer is up to date
+            Err(_) => !self.layer.keys(&[]).is_ok_and(|keys| keys.is_empty()),
+        }
     }
 
     pub fn save_layer(&self) -> Result<()> {
-        std::fs::write(&self.layer_path, self.layer.to_string())?;
+        std::fs::write(&self.layer_path, self.layer.to_layer_string()?)
+            .with_context(|| format!("Could not write file `{:?}`", &self.layer_path))?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, NamedTempFile};
+    use toml_edit::DocumentMut;
+
+    fn fixture_model(layer_path: std::path::PathBuf) -> Model {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tcp_proto_messages (session INT, proto TEXT, timestamp TEXT, data TEXT);
+             INSERT INTO tcp_proto_messages VALUES (1, 'tcp_login', '2024-01-01 00:00:00', '{\"a\": 1}');
+             INSERT INTO tcp_proto_messages VALUES (NULL, 'tcp_move', '2024-01-01 00:00:01', '{\"b\": 2}');
+             INSERT INTO tcp_proto_messages VALUES (2, 'udp_ping', '2024-01-01 00:00:02', '{}');",
+        )
+        .unwrap();
+
+        Model::from_connection(conn, DocumentMut::new().into(), layer_path)
+    }
+
+    #[test]
+    fn is_connected_rejects_a_file_not_being_a_database() {
+        let model = fixture_model(PathBuf::new());
+        assert!(model.is_connected());
+
+        let file = NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            "not a database, but long enough to hold a header".repeat(4),
+        )
+        .unwrap();
+        let conn = Connection::open(file.path()).unwrap();
+        let model = Model::from_connection(conn, DocumentMut::new().into(), PathBuf::new());
+        assert!(!model.is_connected());
+    }
+
+    #[test]
+    fn list_tables_and_columns_describe_the_schema() {
+        let mut model = fixture_model(PathBuf::new());
+
+        assert_eq!(model.list_tables().unwrap(), vec!["tcp_proto_messages"]);
+        let columns: Vec<(String, String)> = model
+            .list_columns("tcp_proto_messages")
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.name, c.type_affinity))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                ("session".into(), "INTEGER".into()),
+                ("proto".into(), "TEXT".into()),
+                ("timestamp".into(), "TEXT".into()),
+                ("data".into(), "TEXT".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn execute_custom_query_returns_text_rows() {
+        let mut model = fixture_model(PathBuf::new());
+        let sql = "SELECT proto, session FROM tcp_proto_messages WHERE proto LIKE 'tcp%'";
+
+        let rows = model.execute_custom_query(sql).unwrap();
+
+        assert_eq!(
+            model.custom_query_columns(sql).unwrap(),
+            vec!["proto", "session"]
+        );
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["proto"], "tcp_login");
+        assert_eq!(rows[1]["session"], "NULL");
+        assert!(model.execute_custom_query("SELECT * FROM missing").is_err());
+    }
+
+    #[test]
+    fn query_protos_returns_all_rows() {
+        let mut model = fixture_model(PathBuf::new());
+
+        let protos = model.query_protos().unwrap();
+
+        assert_eq!(
+            protos,
+            vec![
+                Header {
+                    data_size: Some(8),
+                    ..Header::from(
+                        1,
+                        Some(1),
+                        String::from("tcp_login"),
+                        String::from("2024-01-01 00:00:00")
+                    )
+                },
+                Header {
+                    data_size: Some(8),
+                    ..Header::from(
+                        2,
+                        None,
+                        String::from("tcp_move"),
+                        String::from("2024-01-01 00:00:01")
+                    )
+                },
+                Header {
+                    data_size: Some(2),
+                    ..Header::from(
+                        3,
+                        Some(2),
+                        String::from("udp_ping"),
+                        String::from("2024-01-01 00:00:02")
+                    )
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn query_protos_sorted_orders_in_sqlite() {
+        let mut model = fixture_model(PathBuf::new());
+        let rowids = |model: &mut Model, filter: &SqlFilter, key, descending| {
+            model
+                .query_protos_sorted(filter, &SortConfig { key, descending })
+                .unwrap()
+                .iter()
+                .map(|h| h.rowid)
+                .collect::<Vec<_>>()
+        };
+        let all = SqlFilter::default();
+
+        assert_eq!(rowids(&mut model, &all, SortKey::Name, true), vec![3, 2, 1]);
+        // NULL sessions first, as `None` sorts before any id
+        assert_eq!(
+            rowids(&mut model, &all, SortKey::SessionId, false),
+            vec![2, 1, 3]
+        );
+        // equal sizes broken by rowid, in the same direction
+        assert_eq!(
+            rowids(&mut model, &all, SortKey::DataSize, false),
+            vec![3, 1, 2]
+        );
+        assert_eq!(
+            rowids(&mut model, &all, SortKey::DataSize, true),
+            vec![2, 1, 3]
+        );
+
+        let tcp = SqlFilter {
+            name_like: Some(String::from("tcp%")),
+            ..SqlFilter::default()
+        };
+        assert_eq!(
+            rowids(&mut model, &tcp, SortKey::Timestamp, true),
+            vec![2, 1]
+        );
+    }
+
+    #[test]
+    fn query_protos_iter_walks_every_page() {
+        let model = fixture_model(PathBuf::new());
+        model
+            .conn
+            .execute_batch(
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1200)
+                 INSERT INTO tcp_proto_messages SELECT NULL, 'bulk', '', '' FROM n;",
+            )
+            .unwrap();
+
+        let protos = model.query_protos_iter().unwrap();
+
+        assert_eq!(protos.count(), 1203);
+        assert_eq!(model.query_protos_iter().unwrap().take(10).count(), 10);
+    }
+
+    #[test]
+    fn query_protos_skips_unreadable_rows() {
+        let mut model = fixture_model(PathBuf::new());
+        model
+            .conn
+            .execute(
+                "INSERT INTO tcp_proto_messages VALUES (3, NULL, '2024-01-01 00:00:03', '{}')",
+                [],
+            )
+            .unwrap();
+
+        let names: Vec<String> = model
+            .query_protos()
+            .unwrap()
+            .into_iter()
+            .map(|header| header.name)
+            .collect();
+
+        assert_eq!(names, vec!["tcp_login", "tcp_move", "udp_ping"]);
+    }
+
+    #[test]
+    fn query_protos_filtered_applies_every_condition() {
+        let mut model = fixture_model(PathBuf::new());
+        let names = |model: &mut Model, filter: &str| -> Vec<String> {
+            model
+                .query_protos_filtered(&filter.parse().unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|h| h.name)
+                .collect()
+        };
+
+        assert_eq!(
+            names(&mut model, "name=tcp%"),
+            vec!["tcp_login", "tcp_move"]
+        );
+        assert_eq!(names(&mut model, "name=tcp% session=1"), vec!["tcp_login"]);
+        assert_eq!(
+            names(
+                &mut model,
+                "from=2024-01-01 00:00:01 to=2024-01-01 00:00:01"
+            ),
+            vec!["tcp_move"]
+        );
+        assert_eq!(names(&mut model, "").len(), 3);
+        assert!("session=one".parse::<SqlFilter>().is_err());
+        assert!("color=red".parse::<SqlFilter>().is_err());
+    }
+
+    #[test]
+    fn query_data_bytes_caches_the_data() {
+        let mut model = fixture_model(PathBuf::new());
+        model.set_cache_size(NonZeroUsize::new(1).unwrap());
+
+        model.query_data_bytes(&1).unwrap();
+        model.query_data_bytes(&1).unwrap();
+        model.query_data_bytes(&2).unwrap();
+        model.query_data_bytes(&1).unwrap();
+        assert_eq!(model.cache_stats(), (1, 3));
+
+        model.delete_proto(1).unwrap();
+        assert!(model.query_data_bytes(&1).is_err());
+    }
+
+    #[test]
+    fn query_data_bytes_returns_the_row_data() {
+        let mut model = fixture_model(PathBuf::new());
+
+        assert_eq!(model.query_data_bytes(&2).unwrap(), b"{\"b\": 2}");
+        assert!(model.query_data_bytes(&42).is_err());
+    }
+
+    #[test]
+    fn set_layer_key_updates_the_layer() {
+        let mut model = fixture_model(PathBuf::new());
+
+        model
+            .set_layer_key("alias", toml_edit::value("login"))
+            .unwrap();
+
+        assert_eq!(model.layer.get(&["alias"]).unwrap().as_str(), Some("login"));
+        assert!(model.set_layer_key("", toml_edit::value("x")).is_err());
+    }
+
+    #[test]
+    fn delete_layer_key_removes_nested_keys() {
+        let mut model = fixture_model(PathBuf::new());
+        model.layer =
+            LayerRepr::parse("[views]\nlogins = \"SELECT 1\"\n", LayerFormat::Toml).unwrap();
+
+        model.delete_layer_key(&["views", "logins"]).unwrap();
+
+        assert!(model.layer.get(&["views", "logins"]).is_none());
+        assert!(model.delete_layer_key(&["views", "logins"]).is_err());
+    }
+
+    #[test]
+    fn set_layer_key_at_path_creates_nested_tables() {
+        let dir = tempdir().unwrap();
+        let layer_path = dir.path().join("layer.toml");
+        let mut model = fixture_model(layer_path.clone());
+
+        model
+            .set_layer_key_at_path(&["protocols", "tcp", "port"], toml_edit::value(80))
+            .unwrap();
+        model.save_layer().unwrap();
+
+        let saved = fs::read_to_string(&layer_path).unwrap();
+        assert_eq!(saved, "[protocols]\n\n[protocols.tcp]\nport = 80\n");
+        assert!(model
+            .set_layer_key_at_path(&["protocols", ""], toml_edit::value(1))
+            .is_err());
+    }
+
+    #[test]
+    fn layer_as_json_pretty_prints_the_layer() {
+        let mut model = fixture_model(PathBuf::new());
+        model.layer =
+            LayerRepr::parse("name = \"x\"\n[views]\nall = 1\n", LayerFormat::Toml).unwrap();
+
+        assert_eq!(
+            model.layer_as_json().unwrap(),
+            "{\n  \"name\": \"x\",\n  \"views\": {\n    \"all\": 1\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn bookmarks_round_trip_through_the_layer() {
+        let mut model = fixture_model(PathBuf::new());
+        assert!(model.query_bookmarks().is_empty());
+
+        model.set_bookmarks(&HashSet::from([3, 1])).unwrap();
+
+        assert_eq!(
+            model.layer.get(&["bookmarks"]).unwrap().to_string().trim(),
+            "[1, 3]"
+        );
+        assert_eq!(model.query_bookmarks(), HashSet::from([1, 3]));
+    }
+
+    #[test]
+    fn merge_layer_resolves_conflicts_with_the_strategy() {
+        let dir = tempdir().unwrap();
+        let other_path = dir.path().join("other.toml");
+        fs::write(&other_path, "name = \"theirs\"\nextra = 1\nsame = true\n").unwrap();
+        let merged = |strategy| {
+            let mut model = fixture_model(dir.path().join("layer.toml"));
+            model.layer =
+                LayerRepr::parse("name = \"ours\"\nsame = true\n", LayerFormat::Toml).unwrap();
+            let conflicts = model.merge_layer(&other_path, strategy).unwrap();
+            (model.layer, conflicts)
+        };
+
+        let (layer, conflicts) = merged(MergeStrategy::Ours);
+        assert_eq!(layer.get(&["name"]).unwrap().as_str(), Some("ours"));
+        assert_eq!(layer.get(&["extra"]).unwrap().as_integer(), Some(1));
+        assert!(conflicts.is_empty());
+
+        let (layer, _) = merged(MergeStrategy::Theirs);
+        assert_eq!(layer.get(&["name"]).unwrap().as_str(), Some("theirs"));
+
+        let (layer, conflicts) = merged(MergeStrategy::Interactive);
+        assert_eq!(layer.get(&["name"]).unwrap().as_str(), Some("ours"));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].key, "name");
+        assert_eq!(conflicts[0].ours, "\"ours\"");
+    }
+
+    #[test]
+    fn layer_json_export_and_import_round_trip() {
+        let dir = tempdir().unwrap();
+        let json_path = dir.path().join("layer.json");
+        let mut model = fixture_model(dir.path().join("layer.toml"));
+        model.layer =
+            LayerRepr::parse("name = \"theirs\"\n[section]\nkey = 1\n", LayerFormat::Toml).unwrap();
+        model.export_layer_json(&json_path).unwrap();
+        let exported: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(exported["section"]["key"], 1);
+
+        let mut other = fixture_model(dir.path().join("other.toml"));
+        other.layer = LayerRepr::parse("name = \"ours\"\n", LayerFormat::Toml).unwrap();
+        other.import_layer_json(&json_path).unwrap();
+        assert_eq!(other.layer.get(&["name"]).unwrap().as_str(), Some("ours"));
+        assert_eq!(
+            other.layer.get(&["section", "key"]).unwrap().as_integer(),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn layer_diff_reports_added_and_removed_lines() {
+        let old = LayerRepr::parse("a = 1\nb = 2\n", LayerFormat::Toml).unwrap();
+        let new = LayerRepr::parse("a = 1\nb = 3\nc = 4\n", LayerFormat::Toml).unwrap();
+
+        assert_eq!(
+            Model::layer_diff(&old, &new).unwrap(),
+            vec![
+                DiffLine::Unchanged(String::from("a = 1")),
+                DiffLine::Removed(String::from("b = 2")),
+                DiffLine::Added(String::from("b = 3")),
+                DiffLine::Added(String::from("c = 4")),
+            ]
+        );
+    }
+
+    #[test]
+    fn data_diff_pairs_lines_side_by_side() {
+        let rows = Model::data_diff("a\nb\nc\n", "a\nB\nc\nd\n");
+
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    Some(DiffLine::Unchanged("a".into())),
+                    Some(DiffLine::Unchanged("a".into()))
+                ),
+                (
+                    Some(DiffLine::Changed("b".into())),
+                    Some(DiffLine::Changed("B".into()))
+                ),
+                (
+                    Some(DiffLine::Unchanged("c".into())),
+                    Some(DiffLine::Unchanged("c".into()))
+                ),
+                (None, Some(DiffLine::Added("d".into()))),
+            ]
+        );
+    }
+
+    #[test]
+    fn save_layer_writes_the_layer_file() {
+        let dir = tempdir().unwrap();
+        let layer_path = dir.path().join("layer.toml");
+        let mut model = fixture_model(layer_path.clone());
+
+        model.set_layer_key("count", toml_edit::value(3)).unwrap();
+        model.save_layer().unwrap();
+
+        let saved = fs::read_to_string(&layer_path).unwrap();
+        assert_eq!(
+            saved.parse::<DocumentMut>().unwrap()["count"].as_integer(),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn layer_edits_persist_across_models() {
+        let layer_file = NamedTempFile::new().unwrap();
+        let layer_path = layer_file.path().to_path_buf();
+
+        let mut model = Model::new(
+            Path::new(":memory:"),
+            layer_path.clone(),
+            LayerFormat::Toml,
+            false,
+        )
+        .unwrap();
+        model.set_layer_key("foo", toml_edit::value("bar")).unwrap();
+        model.save_layer().unwrap();
+
+        let reopened = Model::new(
+            Path::new(":memory:"),
+            layer_path.clone(),
+            LayerFormat::Toml,
+            false,
+        )
+        .unwrap();
+        assert_eq!(reopened.layer.get(&["foo"]).unwrap().as_str(), Some("bar"));
+    }
+
+    #[test]
+    fn is_dirty_compares_the_layer_with_the_saved_file() {
+        let dir = tempdir().unwrap();
+        let layer_path = dir.path().join("layer.json");
+        fs::write(&layer_path, "{\"name\":   \"x\"}").unwrap();
+        let mut model = fixture_model(layer_path.clone());
+        assert!(model.is_dirty());
+
+        // formatted differently but holding the same values
+        model.layer = Model::read_layer(&layer_path, LayerFormat::Json).unwrap();
+        assert!(!model.is_dirty());
+
+        model.set_layer_key("count", toml_edit::value(3)).unwrap();
+        assert!(model.is_dirty());
+        model.save_layer().unwrap();
+        assert!(!model.is_dirty());
+    }
+
+    #[test]
+    fn save_layer_keeps_the_json_format() {
+        let dir = tempdir().unwrap();
+        let layer_path = dir.path().join("layer.json");
+        fs::write(&layer_path, "{\"name\": \"x\"}").unwrap();
+        let mut model = fixture_model(layer_path.clone());
+        model.layer = Model::read_layer(&layer_path, LayerFormat::Json).unwrap();
+
+        model.set_layer_key("count", toml_edit::value(3)).unwrap();
+        model.save_layer().unwrap();
+
+        let saved = fs::read_to_string(&layer_path).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&saved).unwrap(),
+            serde_json::json!({"name": "x", "count": 3})
+        );
+    }
+
+    #[test]
+    fn execute_custom_view_keeps_the_view_order() {
+        let mut model = fixture_model(PathBuf::new());
+        model.layer = LayerRepr::parse(
+            "[views]\nlatest = \"SELECT rowid, session, proto, timestamp FROM tcp_proto_messages ORDER BY timestamp DESC LIMIT 2;\"\n",
+            LayerFormat::Toml,
+        )
+        .unwrap();
+        model.add_tag(3, "ping").unwrap();
+
+        assert_eq!(model.list_views(), vec!["latest"]);
+        let headers = model.execute_custom_view("latest", &[]).unwrap();
+        let names: Vec<&str> = headers.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["udp_ping", "tcp_move"]);
+        assert_eq!(headers[0].tags, vec!["ping"]);
+        assert!(model.execute_custom_view("missing", &[]).is_err());
+    }
+
+    #[test]
+    fn execute_custom_view_binds_the_parameters() {
+        let mut model = fixture_model(PathBuf::new());
+        model.layer = LayerRepr::parse(
+            "[views]\nsession = \"SELECT rowid, session, proto, timestamp FROM tcp_proto_messages WHERE session = :session\"\nunnamed = \"SELECT rowid, session, proto, timestamp FROM tcp_proto_messages WHERE session = ?\"\n",
+            LayerFormat::Toml,
+        )
+        .unwrap();
+
+        assert_eq!(model.view_parameters("session").unwrap(), vec![":session"]);
+        assert!(model.view_parameters("unnamed").is_err());
+        let headers = model
+            .execute_custom_view("session", &[(":session".into(), "2".into())])
+            .unwrap();
+        let names: Vec<&str> = headers.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["udp_ping"]);
+    }
+
+    #[test]
+    fn column_aliases_reads_string_values() {
+        let mut model = fixture_model(PathBuf::new());
+        model.layer = LayerRepr::parse(
+            "[column_aliases]\nproto = \"Protocol Name\"\nsession = 3\n",
+            LayerFormat::Toml,
+        )
+        .unwrap();
+
+        assert_eq!(
+            model.column_aliases(),
+            HashMap::from([("proto".to_owned(), "Protocol Name".to_owned())])
+        );
+    }
+
+    #[test]
+    fn validate_layer_reports_schema_violations() {
+        let dir = tempdir().unwrap();
+        let schema_path = dir.path().join("schema.json");
+        fs::write(
+            &schema_path,
+            r#"{"type": "object", "required": ["name"], "properties": {"name": {"type": "string"}}}"#,
+        )
+        .unwrap();
+        let mut model = fixture_model(dir.path().join("layer.toml"));
+        model.layer =
+            LayerRepr::parse("name = 3\nbookmarks = [1, -2]\n", LayerFormat::Toml).unwrap();
+
+        let errors = model.validate_layer(Some(&schema_path)).unwrap();
+        let default_errors = model.validate_layer(None).unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "/name");
+        assert_eq!(default_errors.len(), 1);
+        assert_eq!(default_errors[0].path, "/bookmarks/1");
+    }
+
+    #[test]
+    fn yaml_layer_round_trips_through_save_and_reload() {
+        let dir = tempdir().unwrap();
+        let layer_path = dir.path().join("layer.yaml");
+        fs::write(&layer_path, "name: x\nsection:\n  foo: 1\n").unwrap();
+        let mut model = fixture_model(layer_path.clone());
+        model.layer = Model::read_layer(&layer_path, LayerFormat::Yaml).unwrap();
+
+        model.set_layer_key("count", toml_edit::value(3)).unwrap();
+        model.save_layer().unwrap();
+        model.reload_layer().unwrap();
+
+        assert_eq!(model.layer.format(), LayerFormat::Yaml);
+        assert_eq!(model.layer.get(&["count"]).unwrap().as_integer(), Some(3));
+        assert_eq!(
+            model.layer.get(&["section", "foo"]).unwrap().as_integer(),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn query_data_bytes_reads_text_and_blobs() {
+        let mut model = fixture_model(PathBuf::new());
+        model
+            .conn
+            .execute(
+                "INSERT INTO tcp_proto_messages VALUES (3, 'bin', '2024-01-01 00:00:03', X'00FF')",
+                [],
+            )
+            .unwrap();
+
+        assert_eq!(model.query_data_bytes(&1).unwrap(), b"{\"a\": 1}");
+        assert_eq!(model.query_data_bytes(&4).unwrap(), vec![0x00, 0xFF]);
+    }
+
+    #[test]
+    fn export_filtered_csv_writes_selected_columns() {
+        let dir = tempdir().unwrap();
+        let csv_path = dir.path().join("export.csv");
+        let mut model = fixture_model(dir.path().join("layer.toml"));
+        let protos = model.query_protos().unwrap();
+        let headers: Vec<&Header> = protos.iter().take(2).collect();
+
+        let written = model
+            .export_filtered_csv(
+                &headers,
+                &[ColumnId::Session, ColumnId::Name, ColumnId::Data],
+                &csv_path,
+            )
+            .unwrap();
+
+        let exported = fs::read_to_string(&csv_path).unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(
+            exported,
+            "session,name,data\n1,tcp_login,\"{\"\"a\"\": 1}\"\n,tcp_move,\"{\"\"b\"\": 2}\"\n"
+        );
+    }
+
+    #[test]
+    fn export_filtered_json_writes_selected_columns() {
+        let dir = tempdir().unwrap();
+        let json_path = dir.path().join("export.json");
+        let mut model = fixture_model(dir.path().join("layer.toml"));
+        let protos = model.query_protos().unwrap();
+        let headers: Vec<&Header> = protos.iter().take(2).collect();
+
+        let written = model
+            .export_filtered_json(&headers, &[ColumnId::Session, ColumnId::Name], &json_path)
+            .unwrap();
+
+        let exported = fs::read_to_string(&json_path).unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&exported).unwrap(),
+            serde_json::json!([
+                {"session": 1, "name": "tcp_login"},
+                {"session": null, "name": "tcp_move"}
+            ])
+        );
+    }
+
+    #[test]
+    fn export_filtered_json_keeps_blobs_and_empties_missing_data() {
+        let dir = tempdir().unwrap();
+        let json_path = dir.path().join("export.json");
+        let mut model = fixture_model(PathBuf::new());
+        model
+            .conn
+            .execute(
+                "INSERT INTO tcp_proto_messages VALUES (3, 'bin', '2024-01-01 00:00:03', X'00FF')",
+                [],
+            )
+            .unwrap();
+        let blob = Header::from(4, Some(3), String::from("bin"), String::from("ts"));
+
+        model
+            .export_filtered_json(&[&blob], &[ColumnId::Data], &json_path)
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&json_path).unwrap())
+                .unwrap(),
+            serde_json::json!([{"data": "\0\u{FFFD}"}])
+        );
+
+        let missing = Header::from(42, None, String::from("gone"), String::from("ts"));
+        let written = model
+            .export_filtered_json(&[&missing, &blob], &[ColumnId::Data], &json_path)
+            .unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&json_path).unwrap())
+                .unwrap(),
+            serde_json::json!([{"data": ""}, {"data": "\0\u{FFFD}"}])
+        );
+
+        let csv_path = dir.path().join("export.csv");
+        let written = model
+            .export_filtered_csv(&[&missing], &[ColumnId::RowId, ColumnId::Data], &csv_path)
+            .unwrap();
+        assert_eq!(written, 1);
+        assert_eq!(fs::read_to_string(&csv_path).unwrap(), "rowid,data\n42,\n");
+    }
+
+    #[test]
+    fn writes_fail_on_a_read_only_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("capture.db");
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE tcp_proto_messages (session INT, proto TEXT, timestamp TEXT, data TEXT);",
+            )
+            .unwrap();
+        let layer_path = dir.path().join("layer.toml");
+        fs::write(&layer_path, "").unwrap();
+
+        let model = Model::new(&db_path, layer_path.clone(), LayerFormat::Toml, false).unwrap();
+        assert!(!model.is_writable());
+        let err = model.set_annotation(1, "login").unwrap_err();
+        assert_eq!(err.to_string(), "Database opened read-only");
+        assert!(model.add_tag(1, "auth").is_err());
+
+        let model = Model::new(&db_path, layer_path.clone(), LayerFormat::Toml, true).unwrap();
+        assert!(model.is_writable());
+        model.set_annotation(1, "login").unwrap();
+    }
+
+    #[test]
+    fn missing_layer_is_reported_until_created() {
+        let dir = tempdir().unwrap();
+        let layer_path = dir.path().join("layer.json");
+
+        let err = Model::new(
+            Path::new(":memory:"),
+            layer_path.clone(),
+            LayerFormat::Json,
+            false,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Layer file '{}' not found. Use --create-layer to create it.",
+                layer_path.display()
+            )
+        );
+
+        Model::create_layer(&layer_path, LayerFormat::Json).unwrap();
+        let model = Model::new(
+            Path::new(":memory:"),
+            layer_path.clone(),
+            LayerFormat::Json,
+            false,
+        )
+        .unwrap();
+        assert!(model.query_layer_entries(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn import_annotations_upserts_rows() {
+        let dir = tempdir().unwrap();
+        let csv_path = dir.path().join("annotations.csv");
+        let mut model = fixture_model(PathBuf::new());
+        assert_eq!(model.query_annotation(1).unwrap(), None);
+
+        fs::write(&csv_path, "rowid,annotation\n1,login\n3,ping\n").unwrap();
+        assert_eq!(model.import_annotations(&csv_path).unwrap(), 2);
+        fs::write(&csv_path, "rowid,annotation\n1,first login\n").unwrap();
+        assert_eq!(model.import_annotations(&csv_path).unwrap(), 1);
+
+        assert_eq!(
+            model.query_annotation(1).unwrap().as_deref(),
+            Some("first login")
+        );
+        assert_eq!(model.query_annotation(2).unwrap(), None);
+        assert_eq!(model.query_annotation(3).unwrap().as_deref(), Some("ping"));
+    }
+
+    #[test]
+    fn query_protos_joins_annotations() {
+        let mut model = fixture_model(PathBuf::new());
+        model.set_annotation(2, "moved").unwrap();
+
+        let protos = model.query_protos().unwrap();
+        let annotations: Vec<Option<&str>> =
+            protos.iter().map(|p| p.annotation.as_deref()).collect();
+        assert_eq!(annotations, vec![None, Some("moved"), None]);
+    }
+
+    #[test]
+    fn count_protos_by_session_groups_every_row() {
+        let mut model = fixture_model(PathBuf::new());
+        model
+            .conn
+            .execute(
+                "INSERT INTO tcp_proto_messages VALUES (1, 'tcp_logout', '2024-01-01 00:00:03', '{}')",
+                [],
+            )
+            .unwrap();
+
+        let counts = model.count_protos_by_session().unwrap();
+        assert_eq!(
+            counts,
+            HashMap::from([(Some(1), 2), (Some(2), 1), (None, 1)])
+        );
+    }
+
+    #[test]
+    fn query_protos_aggregates_tags() {
+        let mut model = fixture_model(PathBuf::new());
+        model.add_tag(1, "login").unwrap();
+        model.add_tag(1, "auth").unwrap();
+        model.add_tag(3, "login").unwrap();
+        model.add_tag(3, "net").unwrap();
+        model.remove_tag(3, "net").unwrap();
+        assert!(model.add_tag(2, "two words").is_err());
+
+        let protos = model.query_protos().unwrap();
+        assert_eq!(protos[0].tags, vec!["auth", "login"]);
+        assert!(protos[1].tags.is_empty());
+        assert_eq!(protos[2].tags, vec!["login"]);
+        assert_eq!(model.query_unique_tags().unwrap(), vec!["auth", "login"]);
+    }
+
+    #[test]
+    fn add_proto_returns_the_new_rowid() {
+        let mut model = fixture_model(PathBuf::new());
+
+        let rowid = model
+            .add_proto(None, "tcp_new", "2024-01-01 00:00:03", "{\"c\": 3}")
+            .unwrap();
+
+        assert_eq!(rowid, 4);
+        let protos = model.query_protos().unwrap();
+        assert_eq!(protos[3].name, "tcp_new");
+        assert_eq!(protos[3].session_id, None);
+        assert_eq!(model.query_data_bytes(&rowid).unwrap(), b"{\"c\": 3}");
+    }
+
+    #[test]
+    fn duplicate_proto_appends_a_copy() {
+        let mut model = fixture_model(PathBuf::new());
+
+        let copy = model.duplicate_proto(1).unwrap();
+
+        assert_eq!(copy, 4);
+        let protos = model.query_protos().unwrap();
+        assert_eq!(protos[3].name, "tcp_login (copy)");
+        assert_eq!(protos[3].session_id, Some(1));
+        assert_eq!(protos[3].timestamp, protos[0].timestamp);
+        assert_eq!(model.query_data_bytes(&copy).unwrap(), b"{\"a\": 1}");
+        assert!(model.duplicate_proto(42).is_err());
+    }
+
+    #[test]
+    fn delete_proto_removes_the_row_and_its_metadata() {
+        let mut model = fixture_model(PathBuf::new());
+        model.set_annotation(2, "moved").unwrap();
+        model.add_tag(2, "net").unwrap();
+        model.add_tag(3, "net").unwrap();
+
+        model.delete_proto(2).unwrap();
+
+        let protos = model.query_protos().unwrap();
+        let rowids: Vec<usize> = protos.iter().map(|p| p.rowid).collect();
+        assert_eq!(rowids, vec![1, 3]);
+        assert_eq!(model.query_annotation(2).unwrap(), None);
+        assert_eq!(protos[1].tags, vec!["net"]);
+        // a fresh rowid reusing the deleted one must not inherit its tags
+        model
+            .conn
+            .execute(
+                "INSERT INTO tcp_proto_messages (rowid, session, proto, timestamp, data)
+                 VALUES (2, 1, 'tcp_new', '2024-01-01 00:00:03', '{}')",
+                [],
+            )
+            .unwrap();
+        assert!(model.query_protos().unwrap()[1].tags.is_empty());
+    }
+}
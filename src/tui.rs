@@ -0,0 +1,69 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use ratatui::{
+    crossterm::{
+        event::{DisableMouseCapture, EnableMouseCapture},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    prelude::CrosstermBackend,
+    Terminal,
+};
+
+pub type Tui = Terminal<CrosstermBackend<io::Stdout>>;
+
+// whether the terminal is currently in raw/alternate mode, so restore() is idempotent
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+// whether mouse events are captured, skipped with `--no-mouse`
+static MOUSE_CAPTURED: AtomicBool = AtomicBool::new(false);
+
+pub fn init(mouse: bool) -> io::Result<Tui> {
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    if mouse {
+        execute!(io::stdout(), EnableMouseCapture)?;
+        MOUSE_CAPTURED.store(true, Ordering::SeqCst);
+    }
+    enable_raw_mode()?;
+    INITIALIZED.store(true, Ordering::SeqCst);
+    set_panic_hook();
+    Terminal::new(CrosstermBackend::new(io::stdout()))
+}
+
+fn set_panic_hook() {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        ratatui::restore();
+        let _ = restore();
+        hook(panic_info);
+    }));
+}
+
+pub fn restore() -> io::Result<()> {
+    if !INITIALIZED.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    if MOUSE_CAPTURED.swap(false, Ordering::SeqCst) {
+        execute!(io::stdout(), DisableMouseCapture)?;
+    }
+    disable_raw_mode()?;
+    Ok(())
+}
+
+// flag raised when the process is asked to terminate, checked by the event loop
+pub fn register_signal_handlers() -> io::Result<Arc<AtomicBool>> {
+    let terminate = Arc::new(AtomicBool::new(false));
+
+    // Ctrl+C is delivered as a key event by crossterm in raw mode, so only
+    // signals sent from outside (kill, process supervisors) land here
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::{SIGINT, SIGTERM};
+        signal_hook::flag::register(SIGTERM, Arc::clone(&terminate))?;
+        signal_hook::flag::register(SIGINT, Arc::clone(&terminate))?;
+    }
+
+    Ok(terminate)
+}
@@ -5,16 +5,27 @@ use ratatui::{
     style::{palette::tailwind, Color, Modifier, Style, Stylize},
     text::{Line, Span, Text},
     widgets::{
-        Block, Borders, Cell, Clear, Paragraph, Row, Scrollbar, ScrollbarOrientation, Table,
-        Widget, Wrap,
+        Block, Borders, Cell, Clear, Paragraph, Row, Scrollbar, ScrollbarOrientation, Sparkline,
+        Table, Widget, Wrap,
     },
     Frame,
 };
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Instant;
+use unicode_width::UnicodeWidthStr;
 
 use crate::{
-    app::{AppState, CurrentScreen},
-    component::{EditingInput, InputArena, MainInput},
-    model::Header,
+    app::{
+        filter_has_negation, AppState, CurrentScreen, DataViewMode, FilterHighlighter, FilterMode,
+        SaveStatus, Stats,
+    },
+    component::{EditingInput, InputArena, InputId, MainInput, ValueType},
+    config::{KeyBindings, Theme},
+    header::Header,
+    layer::LayerFormat,
+    model::{ColumnId, ColumnInfo, DiffLine, LayerEntry, QueryRow, SortConfig, SortKey},
 };
 
 const FOCUSED_TEXT_COLOR: Color = Color::Green;
@@ -27,116 +38,476 @@ const SELECTED_CELL_STYLE_FG: Color = tailwind::SLATE.c600;
 const NORMAL_ROW_COLOR: Color = tailwind::SLATE.c950;
 const ALT_ROW_COLOR: Color = tailwind::SLATE.c900;
 const ROW_FG: Color = tailwind::SLATE.c200;
+const ADDED_ROW_BG: Color = tailwind::GREEN.c400;
 const BUFFER_BG: Color = tailwind::SLATE.c950;
 
+const BOOKMARK_COLOR: Color = tailwind::YELLOW.c400;
+const TAG_COLORS: [Color; 6] = [
+    tailwind::BLUE.c600,
+    tailwind::EMERALD.c600,
+    tailwind::AMBER.c600,
+    tailwind::ROSE.c600,
+    tailwind::VIOLET.c600,
+    tailwind::CYAN.c600,
+];
+
 const ACTIVE_STYLE: Style = Style::new().bg(ALT_ROW_COLOR).fg(Color::Black);
 const INACTIVE_STYLE: Style = Style::new().bg(NORMAL_ROW_COLOR).fg(Color::Black);
+const OVERFLOW_STYLE: Style = Style::new().fg(Color::Red);
+const TITLE_NAME_WIDTH: usize = 30;
+const SPINNER: &str = "⣾⣽⣻⢿⡿⣟⣯⣷";
+
+// centered popup of at most `max_width` columns and `max_height` rows, whatever the
+// size of the terminal
+fn sized_rect(max_width: u16, max_height: u16, r: Rect) -> Rect {
+    let width = max_width.min(r.width);
+    let height = max_height.min(r.height);
+    Rect {
+        x: r.x + (r.width - width) / 2,
+        y: r.y + (r.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+// largest size of a confirmation popup, in characters
+#[derive(Clone, Copy)]
+pub struct PopupConfig {
+    pub width: u16,
+    pub height: u16,
+}
+
+const EXIT_POPUP: PopupConfig = PopupConfig {
+    width: 70,
+    height: 8,
+};
+const SAVE_POPUP: PopupConfig = PopupConfig {
+    width: 100,
+    height: 24,
+};
+const DELETE_POPUP: PopupConfig = PopupConfig {
+    width: 70,
+    height: 7,
+};
+
+// yes/no popup answered with y/n, returns the space left between the message and the
+// buttons for screens showing more context (e.g. a diff)
+fn render_confirmation_dialog(
+    frame: &mut Frame,
+    message: &str,
+    yes_label: &str,
+    no_label: &str,
+    popup: PopupConfig,
+) -> Rect {
+    let area = sized_rect(popup.width, popup.height, frame.area());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().bg(BUFFER_BG));
+    let inner = block.inner(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
 
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
-    // cut r (the given Rect) in 3 parts
-    let popup_layout = Layout::default()
+    let layout = Layout::default()
         .direction(ratatui::layout::Direction::Vertical)
         .constraints([
-            Constraint::Percentage((100 - percent_y) / 2),
-            Constraint::Percentage(percent_y),
-            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Length(2),
+            Constraint::Min(0),
+            Constraint::Length(1),
         ])
-        .split(r);
+        .split(inner);
 
-    // Then cut the middle vectical piece into 3 width-wise pieces
-    Layout::default()
-        .direction(ratatui::layout::Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage((100 - percent_x) / 2),
-            Constraint::Percentage(100 - percent_x),
-            Constraint::Percentage((100 - percent_x) / 2),
-        ])
-        .split(popup_layout[1])[1] // return the middle chunk
+    let message = Paragraph::new(Text::styled(message, Style::default().fg(Color::Red)))
+        .wrap(Wrap { trim: false });
+    frame.render_widget(message, layout[0]);
+
+    let buttons = Line::from(vec![
+        Span::styled(
+            format!(" (y) {yes_label} "),
+            Style::default().fg(Color::Black).bg(Color::Green),
+        ),
+        Span::raw("   "),
+        Span::styled(
+            format!(" (n) {no_label} "),
+            Style::default().fg(Color::Black).bg(Color::Red),
+        ),
+    ])
+    .centered();
+    frame.render_widget(Paragraph::new(buttons), layout[2]);
+
+    layout[1]
 }
 
-fn build_title() -> impl Widget {
+// keeps the end of names longer than `max` characters, which is the most telling part
+fn truncate_start(name: &str, max: usize) -> String {
+    let count = name.chars().count();
+    if count <= max {
+        return name.to_owned();
+    }
+    let tail: String = name.chars().skip(count - (max - 3)).collect();
+    format!("...{tail}")
+}
+
+fn build_title(
+    theme: &Theme,
+    active_view: Option<&str>,
+    db_name: &str,
+    layer_name: &str,
+    dirty: bool,
+) -> impl Widget {
     let title_block = Block::default()
         .borders(Borders::ALL)
         .style(Style::default());
 
-    Paragraph::new(Text::styled(
-        "Semantic Layer Builder",
-        Style::default().fg(FOCUSED_TEXT_COLOR),
-    ))
-    .block(title_block)
+    let mut title = vec![
+        Span::styled("Semantic Layer Builder", Style::default().fg(theme.accent)),
+        Span::styled(
+            format!(
+                " | {} → {}",
+                truncate_start(db_name, TITLE_NAME_WIDTH),
+                truncate_start(layer_name, TITLE_NAME_WIDTH)
+            ),
+            Style::default().fg(ROW_FG),
+        ),
+    ];
+    if dirty {
+        title.push(Span::styled(" [●]", Style::default().fg(Color::Yellow)));
+    }
+    if let Some(view) = active_view {
+        title.push(Span::styled(
+            format!(" — view: {view}"),
+            Style::default().fg(Color::Cyan),
+        ));
+    }
+
+    Paragraph::new(Line::from(title)).block(title_block)
 }
 
-fn build_search_proto_name(input: &InputArena, screen: &CurrentScreen) -> Result<impl Widget> {
+fn build_search_proto_name(
+    input: &InputArena,
+    bookmarks_only: bool,
+    sql_filter: bool,
+    match_count: usize,
+    total_count: usize,
+    overflow: bool,
+) -> Result<impl Widget> {
+    let mut title = vec![Span::styled(
+        if bookmarks_only {
+            "Filter (bookmarks only)"
+        } else {
+            "Filter"
+        },
+        Style::default().fg(FOCUSED_TEXT_COLOR),
+    )];
+    let filter = input.get_content(&MainInput::Filter.try_into()?)?;
+    if filter_has_negation(filter) {
+        title.push(Span::styled(" [NEG]", Style::default().fg(Color::LightRed)));
+    }
+    // `:where` conditions, only the items loaded being counted
+    if sql_filter {
+        title.push(Span::styled(" [SQL]", Style::default().fg(Color::Cyan)));
+    }
+    // nothing to count when every item matches
+    if match_count != total_count {
+        title.push(Span::styled(
+            format!(" [{match_count}/{total_count}]"),
+            Style::default().fg(if match_count == 0 {
+                Color::Red
+            } else {
+                FOCUSED_TEXT_COLOR
+            }),
+        ));
+    }
     let mut search_block = Block::default()
-        .title(Span::styled(
-            "Filter",
-            Style::default().fg(FOCUSED_TEXT_COLOR),
-        ))
+        .title(Line::from(title))
         .borders(Borders::ALL);
 
-    if let CurrentScreen::Main(MainInput::Filter) = &screen {
+    if input.get(&InputId::Filter)?.is_active() {
         search_block = search_block.style(ACTIVE_STYLE);
     } else {
         search_block = search_block.style(INACTIVE_STYLE);
     }
+    if overflow {
+        search_block = search_block.border_style(OVERFLOW_STYLE);
+    }
 
     Ok(Paragraph::new(Text::styled(
-        input.get_content(&MainInput::Filter.try_into()?)?.clone(),
+        filter.clone(),
         Style::default().fg(FOCUSED_TEXT_COLOR),
     ))
     .block(search_block))
 }
 
-fn build_table<'a>(protos: &[&Header]) -> Table<'a> {
+// bytes as `1.2 KB`, `3 B`...
+pub fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+// header of a proto table column: database column looked up in the aliases, default
+// name and the sort key it matches
+pub fn column_header(column: ColumnId) -> (&'static str, &'static str, SortKey) {
+    match column {
+        ColumnId::RowId => ("rowid", "Row", SortKey::RowId),
+        ColumnId::Session => ("session", "Session", SortKey::SessionId),
+        ColumnId::Name => ("proto", "Name", SortKey::Name),
+        ColumnId::Timestamp => ("timestamp", "Timestamp", SortKey::Timestamp),
+        ColumnId::DataSize => ("size", "Size", SortKey::DataSize),
+        ColumnId::Data => ("data", "Data", SortKey::RowId),
+    }
+}
+
+// heights and proportions of the main screen panes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutConfig {
+    pub title_height: u16,
+    pub footer_height: u16,
+    pub split_percent: u16, // width of the proto list pane
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        LayoutConfig {
+            title_height: 3,
+            footer_height: 3,
+            split_percent: 50,
+        }
+    }
+}
+
+impl LayoutConfig {
+    pub fn wide_data() -> Self {
+        LayoutConfig {
+            split_percent: 30,
+            ..LayoutConfig::default()
+        }
+    }
+
+    pub fn wide_list() -> Self {
+        LayoutConfig {
+            split_percent: 70,
+            ..LayoutConfig::default()
+        }
+    }
+
+    // terminal rows left to the proto table by the title, filter, table header and footer
+    pub fn visible_rows(&self, terminal_rows: u16) -> usize {
+        usize::from(terminal_rows.saturating_sub(self.title_height + 3 + 1 + self.footer_height))
+    }
+}
+
+impl FromStr for LayoutConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(LayoutConfig::default()),
+            "wide-data" => Ok(LayoutConfig::wide_data()),
+            "wide-list" => Ok(LayoutConfig::wide_list()),
+            _ => Err(anyhow::Error::msg(format!(
+                "Unknown layout `{s}`, expected default, wide-data or wide-list"
+            ))),
+        }
+    }
+}
+
+// drawn left of the selected row of the proto table
+pub const HIGHLIGHT_SYMBOL: &str = " █ ";
+
+#[allow(clippy::too_many_arguments)]
+fn build_table<'a>(
+    protos: &[&Header],
+    columns: &[ColumnId],
+    bookmarks: &HashSet<usize>,
+    multi_selection: &[usize],
+    sort: &SortConfig,
+    column_aliases: &HashMap<String, String>,
+    filter: Option<(&str, FilterMode)>,
+    name_width: Option<usize>, // names being cut to it
+    theme: &Theme,
+) -> Table<'a> {
+    let highlighter = filter.and_then(|(filter, mode)| FilterHighlighter::new(filter, mode));
     let header_style = Style::default().fg(HEADER_COLOR_FG).bg(HEADER_COLOR_BG);
 
     let selected_row_style = Style::default()
         .add_modifier(Modifier::REVERSED)
-        .fg(SELECTED_ROW_STYLE_FG);
+        .fg(theme.highlight);
 
     let selected_cell_style = Style::default()
         .add_modifier(Modifier::REVERSED)
         .fg(SELECTED_CELL_STYLE_FG);
 
-    let header = ["Name", "Session", "Timestamp"]
-        .into_iter()
-        .map(Cell::from)
+    let arrow = if sort.descending { " ↓" } else { " ↑" };
+    let header = columns
+        .iter()
+        .map(|column| {
+            let (db_column, name, key) = column_header(*column);
+            let name = column_aliases.get(db_column).map_or(name, String::as_str);
+            if key == sort.key {
+                Cell::from(format!("{name}{arrow}"))
+            } else {
+                Cell::from(name.to_owned())
+            }
+        })
         .collect::<Row>()
         .style(header_style)
         .height(1);
 
     let rows = protos.iter().map(|item| {
-        Row::new([
-            Cell::from(Text::from(item.name.to_string())),
-            Cell::from(Text::from(if let Some(si) = item.session_id {
-                si.to_string()
+        let annotation = item.annotation.as_ref().filter(|a| !a.is_empty());
+        columns
+            .iter()
+            .map(|column| match column {
+                ColumnId::Name => {
+                    let mut name_line = Vec::new();
+                    let mut bookmark_width = 0;
+                    if bookmarks.contains(&item.rowid) {
+                        name_line.push(Span::styled("★ ", Style::default().fg(BOOKMARK_COLOR)));
+                        bookmark_width = 2;
+                    }
+                    // the tags are cut off first, the full name being in the data pane title
+                    let name = match name_width {
+                        Some(width) => item.display_name(width.saturating_sub(bookmark_width)),
+                        None => Cow::Borrowed(item.name.as_str()),
+                    };
+                    match &highlighter {
+                        Some(highlighter) => name_line.extend(build_highlighted_name(
+                            &name,
+                            &highlighter.positions(&item.name),
+                        )),
+                        None => name_line.push(Span::raw(name.into_owned())),
+                    }
+                    for tag in &item.tags {
+                        name_line.push(Span::raw(" "));
+                        name_line.push(build_tag_badge(tag));
+                    }
+                    let mut name = Text::from(Line::from(name_line));
+                    if let Some(annotation) = annotation {
+                        name.push_line(Line::styled(
+                            annotation.to_string(),
+                            Style::default().fg(UNFOCUSED_TEXT_COLOR),
+                        ));
+                    }
+                    Cell::from(name)
+                }
+                ColumnId::Session => Cell::from(Text::from(if let Some(si) = item.session_id {
+                    si.to_string()
+                } else {
+                    String::from("None")
+                })),
+                ColumnId::Timestamp => Cell::from(Text::from(item.timestamp.to_string())),
+                ColumnId::RowId => Cell::from(Text::from(item.rowid.to_string())),
+                ColumnId::DataSize => Cell::from(Text::from(
+                    item.data_size.map(format_size).unwrap_or_default(),
+                )),
+                // too large for a cell, shown in the data pane instead
+                ColumnId::Data => Cell::default(),
+            })
+            .collect::<Row>()
+            .style(if multi_selection.contains(&item.rowid) {
+                Style::new().fg(theme.accent).bg(ALT_ROW_COLOR).bold()
             } else {
-                String::from("None")
-            })),
-            Cell::from(Text::from(item.timestamp.to_string())),
-        ])
-        .style(Style::new().fg(ROW_FG).bg(NORMAL_ROW_COLOR))
-        .height(1)
+                Style::new().fg(ROW_FG).bg(NORMAL_ROW_COLOR)
+            })
+            .height(if annotation.is_some() { 2 } else { 1 })
     });
 
-    let bar = " █ ";
+    Table::new(rows, vec![Constraint::Min(10); columns.len()])
+        .header(header)
+        .row_highlight_style(selected_row_style)
+        .cell_highlight_style(selected_cell_style)
+        .highlight_symbol(Text::from(HIGHLIGHT_SYMBOL))
+        .highlight_spacing(ratatui::widgets::HighlightSpacing::Always)
+        .bg(BUFFER_BG)
+}
 
+// the matched characters in bold yellow, the others in the row color
+fn build_highlighted_name(name: &str, positions: &[usize]) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (i, c) in name.chars().enumerate() {
+        let matched = positions.contains(&i);
+        if matched != run_matched && !run.is_empty() {
+            spans.push(highlighted_span(std::mem::take(&mut run), run_matched));
+        }
+        run_matched = matched;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        spans.push(highlighted_span(run, run_matched));
+    }
+    spans
+}
+
+fn highlighted_span(text: String, matched: bool) -> Span<'static> {
+    if matched {
+        Span::styled(text, Style::default().fg(Color::Yellow).bold())
+    } else {
+        Span::raw(text)
+    }
+}
+
+// replaces the proto table while the items are read again
+fn build_loading_table<'a>(spinner_frame: u8) -> Table<'a> {
+    let spinner = SPINNER
+        .chars()
+        .nth(usize::from(spinner_frame))
+        .unwrap_or_default();
     Table::new(
-        rows,
-        [
-            Constraint::Min(10),
-            Constraint::Min(10),
-            Constraint::Min(10),
-        ],
+        [Row::new([Cell::from(format!("{spinner} Loading…"))])
+            .style(Style::default().fg(Color::Yellow))],
+        [Constraint::Min(10)],
     )
-    .header(header)
-    .row_highlight_style(selected_row_style)
-    .cell_highlight_style(selected_cell_style)
-    .highlight_symbol(Text::from(bar))
-    .highlight_spacing(ratatui::widgets::HighlightSpacing::Always)
     .bg(BUFFER_BG)
 }
 
+fn build_sql_result_table<'a>(columns: &[String], rows: &[QueryRow]) -> Table<'a> {
+    let header_style = Style::default().fg(HEADER_COLOR_FG).bg(HEADER_COLOR_BG);
+
+    let header = columns
+        .iter()
+        .map(|c| Cell::from(c.clone()))
+        .collect::<Row>()
+        .style(header_style)
+        .height(1);
+
+    let rows = rows.iter().map(|row| {
+        columns
+            .iter()
+            .map(|c| Cell::from(row.get(c).cloned().unwrap_or_default()))
+            .collect::<Row>()
+            .style(Style::new().fg(ROW_FG).bg(NORMAL_ROW_COLOR))
+            .height(1)
+    });
+
+    Table::new(rows, vec![Constraint::Min(10); columns.len()])
+        .header(header)
+        .block(
+            Block::default()
+                .title("Query results (ESC to close)")
+                .borders(Borders::ALL),
+        )
+        .bg(BUFFER_BG)
+}
+
+fn build_tag_badge<'a>(tag: &str) -> Span<'a> {
+    // the same tag always gets the same color
+    let color = TAG_COLORS[tag.bytes().map(usize::from).sum::<usize>() % TAG_COLORS.len()];
+    Span::styled(
+        format!(" {tag} "),
+        Style::default().fg(Color::White).bg(color),
+    )
+}
+
 fn build_scrollbar<'a>() -> Scrollbar<'a> {
     Scrollbar::default()
         .orientation(ScrollbarOrientation::VerticalRight)
@@ -144,12 +515,208 @@ fn build_scrollbar<'a>() -> Scrollbar<'a> {
         .end_symbol(None)
 }
 
-fn build_proto_text(text: String) -> impl Widget {
-    Paragraph::new(text).block(Block::default().borders(Borders::ALL))
+// `> root > section > subsection `, one segment per level of the layer navigation path
+pub fn breadcrumb_segments(path: &[String]) -> Vec<String> {
+    std::iter::once("root")
+        .chain(path.iter().map(String::as_str))
+        .map(|name| format!("> {name} "))
+        .collect()
 }
 
-fn build_mode_footer(screen: &CurrentScreen) -> impl Widget {
-    let current_navigation_text = vec![
+fn build_breadcrumb<'a>(path: &[String]) -> impl Widget + 'a {
+    Paragraph::new(Line::from(
+        breadcrumb_segments(path)
+            .into_iter()
+            .map(Span::raw)
+            .collect::<Vec<_>>(),
+    ))
+    .style(Style::default().fg(tailwind::SLATE.c300))
+}
+
+fn build_layer_json<'a>(json: &str, screen: &CurrentScreen) -> Paragraph<'a> {
+    let mut layer_block = Block::default()
+        .title(Span::styled(
+            "Layer [JSON view - read only]",
+            Style::default().fg(FOCUSED_TEXT_COLOR),
+        ))
+        .borders(Borders::ALL);
+
+    if let CurrentScreen::Main(MainInput::Layer) = &screen {
+        layer_block = layer_block.style(ACTIVE_STYLE);
+    } else {
+        layer_block = layer_block.style(INACTIVE_STYLE);
+    }
+
+    Paragraph::new(json.to_owned())
+        .style(Style::new().fg(ROW_FG).bg(NORMAL_ROW_COLOR))
+        .block(layer_block)
+}
+
+fn build_layer_table<'a>(entries: &[LayerEntry], screen: &CurrentScreen) -> Table<'a> {
+    let header_style = Style::default().fg(HEADER_COLOR_FG).bg(HEADER_COLOR_BG);
+
+    let selected_row_style = Style::default()
+        .add_modifier(Modifier::REVERSED)
+        .fg(SELECTED_ROW_STYLE_FG);
+
+    let header = ["Key", "Value"]
+        .into_iter()
+        .map(Cell::from)
+        .collect::<Row>()
+        .style(header_style)
+        .height(1);
+
+    let rows = entries.iter().map(|entry| {
+        let key = if entry.is_table {
+            format!("▶ {}", entry.key)
+        } else {
+            entry.key.clone()
+        };
+        Row::new([
+            Cell::from(Text::from(key)),
+            Cell::from(Text::from(entry.value.clone())),
+        ])
+        .style(Style::new().fg(ROW_FG).bg(NORMAL_ROW_COLOR))
+        .height(1)
+    });
+
+    let mut layer_block = Block::default()
+        .title(Span::styled(
+            "Layer",
+            Style::default().fg(FOCUSED_TEXT_COLOR),
+        ))
+        .borders(Borders::ALL);
+
+    if let CurrentScreen::Main(MainInput::Layer) = &screen {
+        layer_block = layer_block.style(ACTIVE_STYLE);
+    } else {
+        layer_block = layer_block.style(INACTIVE_STYLE);
+    }
+
+    Table::new(rows, [Constraint::Min(10), Constraint::Min(10)])
+        .header(header)
+        .block(layer_block)
+        .row_highlight_style(selected_row_style)
+        .highlight_spacing(ratatui::widgets::HighlightSpacing::Always)
+        .bg(BUFFER_BG)
+}
+
+const HEX_DUMP_WIDTH: usize = 16; // bytes per hex dump line
+
+// `0000: 48 65 6C 6C 6F 20  Hello ` lines, non printable bytes shown as dots
+fn build_hex_dump<'a>(data: &[u8]) -> Vec<Line<'a>> {
+    data.chunks(HEX_DUMP_WIDTH)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{byte:02X}")).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            Line::from(vec![
+                Span::styled(
+                    format!("{:04X}: ", i * HEX_DUMP_WIDTH),
+                    Style::default().fg(UNFOCUSED_TEXT_COLOR),
+                ),
+                Span::raw(format!(
+                    "{:width$}  ",
+                    hex.join(" "),
+                    width = HEX_DUMP_WIDTH * 3 - 1
+                )),
+                Span::styled(ascii, Style::default().fg(FOCUSED_TEXT_COLOR)),
+            ])
+        })
+        .collect()
+}
+
+fn build_proto_text<'a>(
+    header: Option<&Header>,
+    data: &[u8],
+    mode: DataViewMode,
+) -> impl Widget + 'a {
+    let text = match mode {
+        DataViewMode::Text => Text::raw(String::from_utf8_lossy(data).into_owned()),
+        DataViewMode::Json => match serde_json::from_slice::<serde_json::Value>(data) {
+            Ok(json) => Text::raw(serde_json::to_string_pretty(&json).unwrap_or_default()),
+            Err(_) => Text::raw(String::from_utf8_lossy(data).into_owned()),
+        },
+        DataViewMode::Hex => Text::from(build_hex_dump(data)),
+    };
+    let mode = match mode {
+        DataViewMode::Text => "",
+        DataViewMode::Json => "JSON",
+        DataViewMode::Hex => "Hex",
+    };
+    let title = match header {
+        Some(header) if mode.is_empty() => header.name.clone(),
+        Some(header) => format!("{} ({mode})", header.name),
+        None => mode.to_owned(),
+    };
+
+    Paragraph::new(text).block(Block::default().title(title).borders(Borders::ALL))
+}
+
+// active filter, sort, session and data view, omitted while left to their defaults
+fn build_state_badges(state: &AppState, filter: &str) -> Vec<Span<'static>> {
+    let mut badges = Vec::new();
+    if !filter.is_empty() || state.filter_mode != FilterMode::default() {
+        let mode = match state.filter_mode {
+            FilterMode::Exact => " [=]",
+            FilterMode::Regex => " [rx]",
+            FilterMode::Fuzzy => " [~]",
+        };
+        badges.push(Span::styled(mode, Style::default().fg(Color::Cyan)));
+    }
+    if !filter.is_empty() {
+        badges.push(Span::styled(
+            format!(" [filter: \"{filter}\"]"),
+            Style::default().fg(Color::Cyan),
+        ));
+    }
+    if state.sort_config != SortConfig::default() {
+        let name = match state.sort_config.key {
+            SortKey::RowId => "Row",
+            SortKey::Name => "Name",
+            SortKey::Timestamp => "Timestamp",
+            SortKey::SessionId => "Session",
+            SortKey::DataSize => "Size",
+        };
+        let arrow = if state.sort_config.descending {
+            "↓"
+        } else {
+            "↑"
+        };
+        badges.push(Span::styled(
+            format!(" [sort: {name}{arrow}]"),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    if let Some(session) = state.sql_filter.session_eq {
+        badges.push(Span::styled(
+            format!(" [session: {session}]"),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    let mode = match state.data_view_mode {
+        DataViewMode::Text => None,
+        DataViewMode::Json => Some(" [json]"),
+        DataViewMode::Hex => Some(" [hex]"),
+    };
+    if let Some(mode) = mode {
+        badges.push(Span::styled(mode, Style::default().fg(Color::Green)));
+    }
+    badges
+}
+
+fn build_mode_footer(state: &AppState, command: &str, filter: &str) -> impl Widget {
+    let screen = &state.current_screen;
+    let mut current_navigation_text = vec![
         // the first half of the text
         match screen {
             CurrentScreen::Main(MainInput::None) => {
@@ -158,14 +725,59 @@ fn build_mode_footer(screen: &CurrentScreen) -> impl Widget {
             CurrentScreen::Main(MainInput::Filter) => {
                 Span::styled("Filter Mode", Style::default().fg(Color::White))
             }
+            CurrentScreen::Main(MainInput::Layer) => {
+                Span::styled("Layer Mode", Style::default().fg(Color::Cyan))
+            }
+            CurrentScreen::Main(MainInput::Command) => {
+                Span::styled("Command Mode", Style::default().fg(Color::Magenta))
+            }
             CurrentScreen::Editing(_) => {
                 Span::styled("Editing Mode", Style::default().fg(Color::Yellow))
             }
-            CurrentScreen::Exiting => {
+            CurrentScreen::ConfirmSave { .. } => {
+                Span::styled("Saving Mode", Style::default().fg(Color::Yellow))
+            }
+            CurrentScreen::Annotating { .. } => {
+                Span::styled("Annotating Mode", Style::default().fg(Color::Yellow))
+            }
+            CurrentScreen::Diff { .. } => {
+                Span::styled("Diff Mode", Style::default().fg(Color::Cyan))
+            }
+            CurrentScreen::SchemaView => {
+                Span::styled("Schema Mode", Style::default().fg(Color::Cyan))
+            }
+            CurrentScreen::SqlQuery => {
+                Span::styled("Query Mode", Style::default().fg(Color::Yellow))
+            }
+            CurrentScreen::ViewPicker | CurrentScreen::ViewParameters { .. } => {
+                Span::styled("View Mode", Style::default().fg(Color::Cyan))
+            }
+            CurrentScreen::SessionPicker => {
+                Span::styled("Session Mode", Style::default().fg(Color::Magenta))
+            }
+            CurrentScreen::SizeFilter => {
+                Span::styled("Filter Mode", Style::default().fg(Color::White))
+            }
+            CurrentScreen::Statistics => {
+                Span::styled("Statistics Mode", Style::default().fg(Color::Cyan))
+            }
+            CurrentScreen::MergeConflict { .. } => {
+                Span::styled("Merging Mode", Style::default().fg(Color::Yellow))
+            }
+            CurrentScreen::ConfirmDelete { .. } | CurrentScreen::ConfirmDeleteProto { .. } => {
+                Span::styled("Deleting Mode", Style::default().fg(Color::LightRed))
+            }
+            CurrentScreen::AddProto(_) => {
+                Span::styled("Adding Mode", Style::default().fg(Color::Green))
+            }
+            CurrentScreen::Exiting { .. } => {
                 Span::styled("Exiting Mode", Style::default().fg(Color::LightRed))
             }
         }
         .to_owned(),
+    ];
+    current_navigation_text.extend(build_state_badges(state, filter));
+    current_navigation_text.extend([
         // separator
         Span::styled(" | ", Style::default().fg(Color::White)),
         // the final section of the text, with hints
@@ -182,155 +794,1094 @@ fn build_mode_footer(screen: &CurrentScreen) -> impl Widget {
                 }
             } else if let CurrentScreen::Main(MainInput::Filter) = &screen {
                 Span::styled("Editing filter", Style::default().fg(FOCUSED_TEXT_COLOR))
+            } else if let CurrentScreen::Main(MainInput::Command) = &screen {
+                Span::styled(format!(":{command}"), Style::default().fg(Color::White))
+            } else if let Some(message) = &state.command_message {
+                Span::styled(message.to_owned(), Style::default().fg(Color::Yellow))
             } else {
                 Span::styled("Not Editing", Style::default().fg(UNFOCUSED_TEXT_COLOR))
             }
         },
-    ];
+    ]);
+
+    let save_indicator = match state.save_status {
+        SaveStatus::Clean => Span::raw(""),
+        SaveStatus::Dirty => Span::styled("[●]", Style::default().fg(Color::Yellow)),
+        SaveStatus::Saved => Span::styled("[✓]", Style::default().fg(FOCUSED_TEXT_COLOR)),
+    };
 
     Paragraph::new(Line::from(current_navigation_text))
-        .block(Block::default().borders(Borders::ALL))
+        .block(Block::default().borders(Borders::ALL).title(save_indicator))
 }
 
-fn build_note_footer(screen: &CurrentScreen) -> impl Widget {
+fn build_note_footer(screen: &CurrentScreen, keys: &KeyBindings) -> impl Widget {
     let current_keys_hint = {
         match screen {
             CurrentScreen::Main(MainInput::None) => Span::styled(
-                "(q) quit | (f) filter | (r) refresh | (↑) move up | (↓) move down ",
+                format!("({}) quit | ({}) filter | ({}) refresh | (Tab) layer | (a) annotate | (b) bookmark | (B) bookmarks only | (s/S) sort / reverse | (m) data view | (z) size filter | (g) sessions | (i) statistics | (Space) select | (D) diff | (n) new | (C) duplicate | (Del) delete | (ESC) deselect, unmark, clear filter | (^D) schema | (^W) wrap around | (^F) filter mode | (</>) resize panes | (Q) query | (v) views | (:) command | (x) export | (↑) move up | (↓) move down ", keys.quit, keys.filter, keys.refresh),
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::Main(MainInput::Layer) => Span::styled(
+                "(ESC) / (Tab) quit layer mode | (l) / (Enter) open table | (h) / (Backspace) go back | (d) delete key | (J) JSON view",
                 Style::default().fg(FOCUSED_TEXT_COLOR),
             ),
             CurrentScreen::Main(MainInput::Filter) => Span::styled(
-                "(ESC) / (Enter) quit search mode ",
+                "(ESC) / (Enter) quit search mode | (↑/↓) previous filters | (^F) exact / fuzzy / regex",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::Main(MainInput::Command) => Span::styled(
+                "(ESC) cancel | (Enter) run | export <path> [col,...] | import <path> | merge <path> [strategy] | tag / untag <tag> | tags | columns <col,...>",
                 Style::default().fg(FOCUSED_TEXT_COLOR),
             ),
             CurrentScreen::Editing(_) => Span::styled(
-                "(ESC) cancel | (Tab) switch boxes | (Enter) complete",
+                "(ESC) cancel | (Tab) complete key / switch boxes | (^T) change type | (Enter) complete | (^L) multi-line value | (^S) complete multi-line",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::Diff { .. } | CurrentScreen::SchemaView => Span::styled(
+                "(q) close | (j/k) scroll",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::Statistics => Span::styled(
+                "(i) / (q) / (ESC) close | (+/-) more / less time buckets",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::SizeFilter => Span::styled(
+                "(ESC) cancel | (Enter) apply | min-max in bytes, either bound optional, empty to clear",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::ViewPicker => Span::styled(
+                "(ESC) cancel | (Enter) open view | (↑) move up | (↓) move down",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::ViewParameters { .. } => Span::styled(
+                "(ESC) cancel | (Enter) open view | (Tab) / (↓) next parameter | (↑) previous parameter",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::SessionPicker => Span::styled(
+                "(ESC) cancel | (Enter) keep the session protos | (↑) move up | (↓) move down",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::MergeConflict { .. } => Span::styled(
+                "(o) keep ours | (t) take theirs | (ESC) keep ours for all",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::SqlQuery => Span::styled(
+                "(ESC) cancel | (Enter) run | (^N) new line | (↑/↓) change line",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::Annotating { .. } => Span::styled(
+                "(ESC) cancel | (Enter) save annotation",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::ConfirmSave { .. } => Span::styled(
+                "(y) save | (n) / (ESC) discard | (↑) scroll up | (↓) scroll down",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::ConfirmDelete { .. } | CurrentScreen::ConfirmDeleteProto { .. } => Span::styled(
+                "(y) delete | (n) / (ESC) cancel",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::AddProto(_) => Span::styled(
+                "(ESC) cancel | (Enter) add | (Tab) / (↓) next field | (↑) previous field",
+                Style::default().fg(FOCUSED_TEXT_COLOR),
+            ),
+            CurrentScreen::Exiting { dirty: false } => {
+                Span::styled("", Style::default().fg(Color::Red))
+            }
+            CurrentScreen::Exiting { dirty: true } => Span::styled(
+                "(y) save and quit | (d) quit without saving | (n) cancel",
                 Style::default().fg(FOCUSED_TEXT_COLOR),
             ),
-            CurrentScreen::Exiting => Span::styled("", Style::default().fg(Color::Red)),
         }
     };
 
     Paragraph::new(Line::from(current_keys_hint)).block(Block::default().borders(Borders::ALL))
 }
 
-fn render_main_screen(frame: &mut Frame, state: &mut AppState, input: &InputArena) -> Result<()> {
+fn render_main_screen(
+    frame: &mut Frame,
+    layout: &LayoutConfig,
+    state: &mut AppState,
+    input: &InputArena,
+) -> Result<()> {
     // redesign the main layout
     let layouts = Layout::default()
         .direction(ratatui::layout::Direction::Vertical)
         .constraints([
-            Constraint::Length(3),
+            Constraint::Length(layout.title_height),
             Constraint::Min(1),
-            Constraint::Length(3),
+            Constraint::Length(layout.footer_height),
         ])
         .split(frame.area());
     let middle_layouts = Layout::default()
         .direction(ratatui::layout::Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .constraints([
+            Constraint::Percentage(layout.split_percent),
+            Constraint::Percentage(100 - layout.split_percent),
+        ])
         .split(layouts[1]); // split last part into 2 elements
     let proto_name_layout = Layout::default()
         .direction(ratatui::layout::Direction::Vertical)
         .constraints([Constraint::Length(3), Constraint::Min(1)])
         .split(middle_layouts[0]);
+    let proto_data_layout = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(middle_layouts[1]);
+    let layer_layout = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(1)])
+        .split(proto_data_layout[1]);
 
     let footer_layouts = Layout::default()
         .direction(ratatui::layout::Direction::Horizontal)
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(layouts[2]);
 
-    let title = build_title();
-    let search = build_search_proto_name(input, &state.current_screen)?;
+    let title = build_title(
+        &state.theme,
+        state.active_view.as_deref(),
+        &state.db_name,
+        &state.layer_name,
+        matches!(state.save_status, SaveStatus::Dirty),
+    );
+    let search = build_search_proto_name(
+        input,
+        state.bookmarks_only,
+        state.sql_filter.is_active(),
+        state.get_filtered_count(),
+        state.get_total_count(),
+        state.input_overflow_flash.contains_key(&InputId::Filter),
+    )?;
     let headers: Vec<&Header> = state.get_filtered_data()?;
-    let list = build_table(&headers);
+    let filter = input.get_content(&MainInput::Filter.try_into()?)?;
+    let widths = state.column_widths(proto_name_layout[1].width)?;
+    let name_width = state
+        .visible_columns
+        .iter()
+        .zip(&widths)
+        .find_map(|(column, width)| match (column, width) {
+            (ColumnId::Name, Constraint::Min(width)) => Some(usize::from(*width)),
+            _ => None,
+        });
+    let list = build_table(
+        &headers,
+        &state.visible_columns,
+        &state.bookmarks,
+        &state.multi_selection,
+        &state.sort_config,
+        &state.column_aliases,
+        // the filter being ignored when only bookmarks are listed
+        (!state.bookmarks_only).then_some((filter.as_str(), state.filter_mode)),
+        name_width,
+        &state.theme,
+    )
+    .widths(widths);
+    // the selection jumps to a proto once added
+    let list = match state.added_highlight {
+        Some((rowid, _)) if state.selected_rowid() == Some(rowid) => {
+            list.row_highlight_style(Style::default().fg(Color::Black).bg(ADDED_ROW_BG))
+        }
+        _ => list,
+    };
     let scrollbar = build_scrollbar();
 
-    let text = if let Some((_, cached_data)) = &state.cached {
-        build_proto_text(cached_data.to_string())
+    let text = if let Some((header, cached_data)) = &state.cached {
+        build_proto_text(Some(header), cached_data, state.data_view_mode)
     } else {
-        build_proto_text(String::new())
+        build_proto_text(None, &[], state.data_view_mode)
     };
-    let mode_footer = build_mode_footer(&state.current_screen);
-    let notes_footer = build_note_footer(&state.current_screen);
+    let layer = build_layer_table(&state.layer_entries, &state.current_screen);
+    let layer_json = state
+        .layer_json
+        .as_deref()
+        .map(|json| build_layer_json(json, &state.current_screen));
+    let mode_footer = build_mode_footer(
+        state,
+        input.get_content(&MainInput::Command.try_into()?)?,
+        input.get_content(&InputId::Filter)?,
+    );
+    let notes_footer = build_note_footer(&state.current_screen, &state.keybindings);
 
     // first part will contain the title
     frame.render_widget(title, layouts[0]);
     // second one will contain the list of items
     frame.render_widget(search, proto_name_layout[0]);
-    frame.render_stateful_widget(list, proto_name_layout[1], &mut state.state);
-    frame.render_stateful_widget(scrollbar, proto_name_layout[1], &mut state.scroll_state);
-    frame.render_widget(text, middle_layouts[1]);
+    // kept for the tooltips of the cut names
+    state.table_area = Rect::default();
+    state.name_width = name_width;
+    if let Some((columns, rows)) = &state.sql_result {
+        frame.render_widget(build_sql_result_table(columns, rows), proto_name_layout[1]);
+    } else if state.loading {
+        frame.render_widget(
+            build_loading_table(state.spinner_frame),
+            proto_name_layout[1],
+        );
+    } else {
+        frame.render_stateful_widget(list, proto_name_layout[1], &mut state.state);
+        frame.render_stateful_widget(scrollbar, proto_name_layout[1], &mut state.scroll_state);
+        state.table_area = proto_name_layout[1];
+    }
+    frame.render_widget(text, proto_data_layout[0]);
+    frame.render_widget(build_breadcrumb(&state.layer_nav_path), layer_layout[0]);
+    // kept for mouse clicks on the breadcrumb segments
+    state.breadcrumb_area = layer_layout[0];
+    if let Some(layer_json) = layer_json {
+        frame.render_widget(layer_json, layer_layout[1]);
+    } else {
+        frame.render_stateful_widget(layer, layer_layout[1], &mut state.layer_state);
+    }
     // third part left will contain the mode footer
     frame.render_widget(mode_footer, footer_layouts[0]);
     // third part right will contain the hotkeys footer
     frame.render_widget(notes_footer, footer_layouts[1]);
+    // full name of the cut one under the mouse, moved left to fit in the terminal
+    if let (Some((x, y, name)), CurrentScreen::Main(_)) =
+        (&state.hover_tooltip, &state.current_screen)
+    {
+        let screen = frame.area();
+        let width = u16::try_from(name.width() + 2)
+            .unwrap_or(u16::MAX)
+            .min(screen.width);
+        let area = Rect::new(
+            (*x).min(screen.right() - width),
+            (*y).min(screen.bottom().saturating_sub(1)),
+            width,
+            1,
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(format!(" {name} "))
+                .style(Style::default().fg(ROW_FG).bg(ALT_ROW_COLOR)),
+            area,
+        );
+    }
+    // the oldest toast floats on the row above the footers
+    if let Some(toast) = state.toasts.front() {
+        let row = Rect {
+            y: layouts[2].y.saturating_sub(1),
+            height: 1,
+            ..layouts[2]
+        };
+        let width = u16::try_from(toast.message.width() + 2).unwrap_or(u16::MAX);
+        let area = sized_rect(width, 1, row);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(format!(" {} ", toast.message)).style(toast.style),
+            area,
+        );
+    }
 
     Ok(())
 }
 
+// edits land in the table opened in the layer pane
+fn build_editing_title(layer_format: LayerFormat, nav_path: &[String]) -> String {
+    if nav_path.is_empty() {
+        format!("Enter a new key-value pair ({layer_format} layer)")
+    } else {
+        format!(
+            "Enter a new key-value pair in `{}` ({layer_format} layer)",
+            nav_path.join(".")
+        )
+    }
+}
+
 pub fn render_editing_screen(
     frame: &mut Frame,
     input: &InputArena,
     editing: &EditingInput,
+    value_type: &ValueType,
+    title: String,
+    error: Option<&str>,
+    overflow: &HashMap<InputId, Instant>,
 ) -> Result<()> {
-    let popup_block = Block::default()
-        .title("Enter a new key-value pair")
+    let mut popup_block = Block::default()
+        .title(title)
         .borders(Borders::NONE)
         .style(Style::default().bg(UNFOCUSED_TEXT_COLOR));
 
-    let area = centered_rect(60, 25, frame.area());
+    if let Some(error) = error {
+        popup_block = popup_block.title_bottom(Span::styled(
+            error.to_owned(),
+            Style::default().fg(Color::Red),
+        ));
+    }
+
+    let multiline = input.get(&EditingInput::Value.into())?.is_multiline();
+    // tall enough for a few lines in multi-line mode
+    let area = sized_rect(80, 12, frame.area());
+    frame.render_widget(Clear, area);
     frame.render_widget(popup_block, area);
 
     let popup_chunks = Layout::default()
         .direction(ratatui::layout::Direction::Horizontal)
         .margin(1)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .constraints([
+            Constraint::Percentage(40),
+            Constraint::Percentage(20),
+            Constraint::Percentage(40),
+        ])
         .split(area);
 
     let mut key_block = Block::default().title("Key").borders(Borders::ALL);
-    let mut value_block = Block::default().title("Value").borders(Borders::ALL);
+    let mut value_block = Block::default()
+        .title(if multiline {
+            format!("Value ({value_type}, multi-line)")
+        } else {
+            format!("Value ({value_type})")
+        })
+        .borders(Borders::ALL);
 
-    match editing {
-        EditingInput::Key => key_block = key_block.style(ACTIVE_STYLE),
-        EditingInput::Value => value_block = value_block.style(ACTIVE_STYLE),
+    if input.get(&InputId::Key)?.is_active() {
+        key_block = key_block.style(ACTIVE_STYLE);
+    }
+    if input.get(&InputId::Value)?.is_active() {
+        value_block = value_block.style(ACTIVE_STYLE);
     };
+    if overflow.contains_key(&InputId::Key) {
+        key_block = key_block.border_style(OVERFLOW_STYLE);
+    }
+    if overflow.contains_key(&InputId::Value) {
+        value_block = value_block.border_style(OVERFLOW_STYLE);
+    }
 
     let key_ptr = input.get_content(&EditingInput::Key.into())?;
     let key_text = Paragraph::new(key_ptr.clone()).block(key_block);
     frame.render_widget(key_text, popup_chunks[0]);
 
+    let type_block = Block::default().title("Type").borders(Borders::ALL);
+    let type_text = Paragraph::new(Line::from(vec![
+        Span::styled(
+            value_type.to_string(),
+            Style::default().fg(FOCUSED_TEXT_COLOR),
+        ),
+        Span::styled(" (^T)", Style::default().fg(UNFOCUSED_TEXT_COLOR)),
+    ]))
+    .block(type_block);
+    frame.render_widget(type_text, popup_chunks[1]);
+
     let value_ptr = input.get_content(&EditingInput::Value.into())?;
     let value_text = Paragraph::new(value_ptr.clone()).block(value_block);
-    frame.render_widget(value_text, popup_chunks[1]);
+    frame.render_widget(value_text, popup_chunks[2]);
+
+    let (field_rect, focused) = match editing {
+        EditingInput::Key => (popup_chunks[0], input.get(&InputId::Key)?),
+        EditingInput::Value => (popup_chunks[2], input.get(&InputId::Value)?),
+    };
+    let (offset, line) = focused.cursor_position();
+    // lines below the first one move the field down rather than the helper
+    let line = (line as u16).min(field_rect.height.saturating_sub(3));
+    let field_rect = Rect {
+        y: field_rect.y + line,
+        height: field_rect.height - line,
+        ..field_rect
+    };
+    render_cursor_in_field(frame, field_rect, offset);
 
     Ok(())
 }
 
-pub fn render_exit_screen(frame: &mut Frame) {
-    frame.render_widget(Clear, frame.area());
+// terminal cursor inside a bordered input, kept on its last column when the text
+// is wider than the field
+fn render_cursor_in_field(frame: &mut Frame, field_rect: Rect, cursor_offset: usize) {
+    let max_offset = field_rect.width.saturating_sub(3);
+    let offset = u16::try_from(cursor_offset)
+        .unwrap_or(u16::MAX)
+        .min(max_offset);
+    frame.set_cursor_position((field_rect.x + 1 + offset, field_rect.y + 1));
+}
+
+pub fn render_annotation_screen(frame: &mut Frame, input: &InputArena, rowid: usize) -> Result<()> {
+    let annotation_block = Block::default()
+        .title(format!("Annotation of proto {rowid}"))
+        .borders(Borders::ALL)
+        .style(ACTIVE_STYLE);
+
+    let area = sized_rect(80, 3, frame.area());
+    frame.render_widget(Clear, area);
+
+    let annotation = input.get_content(&InputId::Annotation)?;
+    let annotation_text = Paragraph::new(annotation.clone())
+        .style(Style::default().fg(FOCUSED_TEXT_COLOR))
+        .block(annotation_block);
+    frame.render_widget(annotation_text, area);
+
+    Ok(())
+}
+
+pub fn render_add_proto_screen(
+    frame: &mut Frame,
+    input: &InputArena,
+    focused: &InputId,
+    error: Option<&str>,
+) -> Result<()> {
+    let mut popup_block = Block::default()
+        .title("New proto")
+        .borders(Borders::ALL)
+        .style(Style::default().bg(BUFFER_BG));
+    if let Some(error) = error {
+        popup_block = popup_block.title_bottom(Span::styled(
+            error.to_owned(),
+            Style::default().fg(Color::Red),
+        ));
+    }
+
+    // four inputs and the popup borders
+    let area = sized_rect(80, 14, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup_block, area);
+
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Length(3); 4])
+        .split(area);
+    let fields = [
+        (InputId::NewSession, "Session (empty for none)"),
+        (InputId::NewName, "Name"),
+        (InputId::NewTimestamp, "Timestamp"),
+        (InputId::NewData, "Data"),
+    ];
+    for ((id, title), chunk) in fields.iter().zip(chunks.iter()) {
+        let mut block = Block::default().title(*title).borders(Borders::ALL);
+        if id == focused {
+            block = block.style(ACTIVE_STYLE);
+        }
+        let text = Paragraph::new(input.get_content(id)?.clone()).block(block);
+        frame.render_widget(text, *chunk);
+    }
+
+    Ok(())
+}
+
+pub fn render_confirm_save_screen(frame: &mut Frame, diff: &[DiffLine], scroll: u16) {
+    let lines: Vec<Line> = diff
+        .iter()
+        .map(|line| match line {
+            DiffLine::Unchanged(l) => Line::styled(format!("  {l}"), Style::default().fg(ROW_FG)),
+            DiffLine::Changed(l) => {
+                Line::styled(format!("~ {l}"), Style::default().fg(Color::Yellow))
+            }
+            DiffLine::Added(l) => Line::styled(format!("+ {l}"), Style::default().fg(Color::Green)),
+            DiffLine::Removed(l) => Line::styled(format!("- {l}"), Style::default().fg(Color::Red)),
+        })
+        .collect();
+
+    let area = render_confirmation_dialog(
+        frame,
+        "Save these changes to the layer ?",
+        "Save",
+        "Discard",
+        SAVE_POPUP,
+    );
+    frame.render_widget(Paragraph::new(lines).scroll((scroll, 0)), area);
+}
+
+pub fn render_confirm_delete_screen(frame: &mut Frame, path: &[String]) {
+    let message = format!("Delete `{}` from the layer ?", path.join("."));
+    render_confirmation_dialog(frame, &message, "Delete", "Cancel", DELETE_POPUP);
+}
+
+pub fn render_confirm_delete_proto_screen(frame: &mut Frame, rowid: usize, name: &str) {
+    let message = format!("Delete message {name} (rowid {rowid}) from the database ?");
+    render_confirmation_dialog(frame, &message, "Delete", "Cancel", DELETE_POPUP);
+}
+
+fn build_diff_side<'a>(title: String, lines: Vec<Option<&DiffLine>>, scroll: u16) -> Paragraph<'a> {
+    let lines: Vec<Line> = lines
+        .into_iter()
+        .map(|line| match line {
+            Some(DiffLine::Unchanged(l)) => Line::styled(l.clone(), Style::default().fg(ROW_FG)),
+            Some(DiffLine::Changed(l)) => Line::styled(
+                l.clone(),
+                Style::default().fg(Color::Black).bg(Color::Yellow),
+            ),
+            Some(DiffLine::Added(l)) => Line::styled(
+                l.clone(),
+                Style::default().fg(Color::Black).bg(Color::Green),
+            ),
+            Some(DiffLine::Removed(l)) => {
+                Line::styled(l.clone(), Style::default().fg(Color::Black).bg(Color::Red))
+            }
+            None => Line::default(),
+        })
+        .collect();
+
+    Paragraph::new(lines)
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .scroll((scroll, 0))
+}
+
+pub fn render_diff_screen(
+    frame: &mut Frame,
+    (left, right): (usize, usize),
+    rows: &[(Option<DiffLine>, Option<DiffLine>)],
+    scroll: u16,
+) {
+    // two sides of 80 columns, long lines being the point of a diff
+    let area = sized_rect(160, 40, frame.area());
+    frame.render_widget(Clear, area);
+
+    let sides = Layout::default()
+        .direction(ratatui::layout::Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    // both sides share the scroll offset, keeping matching lines aligned
+    let left_side = build_diff_side(
+        format!("Proto {left}"),
+        rows.iter().map(|(l, _)| l.as_ref()).collect(),
+        scroll,
+    );
+    let right_side = build_diff_side(
+        format!("Proto {right}"),
+        rows.iter().map(|(_, r)| r.as_ref()).collect(),
+        scroll,
+    );
+    frame.render_widget(left_side.style(Style::default().bg(BUFFER_BG)), sides[0]);
+    frame.render_widget(right_side.style(Style::default().bg(BUFFER_BG)), sides[1]);
+}
+
+pub fn render_schema_screen(frame: &mut Frame, schema: &[(String, Vec<ColumnInfo>)], scroll: u16) {
+    let mut lines = Vec::new();
+    for (table, columns) in schema {
+        lines.push(Line::styled(
+            format!("▶ {table}"),
+            Style::default().fg(FOCUSED_TEXT_COLOR).bold(),
+        ));
+        lines.extend(columns.iter().map(|column| {
+            Line::from(vec![
+                Span::styled(format!("    {} ", column.name), Style::default().fg(ROW_FG)),
+                Span::styled(
+                    column.type_affinity.clone(),
+                    Style::default().fg(UNFOCUSED_TEXT_COLOR),
+                ),
+            ])
+        }));
+    }
+
+    let schema_paragraph = Paragraph::new(lines)
+        .block(Block::default().title("Schema").borders(Borders::ALL))
+        .style(Style::default().bg(BUFFER_BG))
+        .scroll((scroll, 0));
+
+    let area = sized_rect(100, 30, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(schema_paragraph, area);
+}
+
+pub fn render_sql_query_screen(
+    frame: &mut Frame,
+    input: &InputArena,
+    error: Option<&str>,
+) -> Result<()> {
+    let mut query_block = Block::default()
+        .title("SQL query")
+        .borders(Borders::ALL)
+        .style(Style::default().bg(BUFFER_BG));
+
+    if let Some(error) = error {
+        query_block = query_block.title_bottom(Span::styled(
+            error.to_owned(),
+            Style::default().fg(Color::Red),
+        ));
+    }
+
+    let query = input.get_lines(&InputId::SqlQuery)?;
+    let lines: Vec<Line> = query
+        .lines()
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == query.line() {
+                Line::styled(format!("> {line}"), Style::default().fg(FOCUSED_TEXT_COLOR))
+            } else {
+                Line::styled(format!("  {line}"), Style::default().fg(ROW_FG))
+            }
+        })
+        .collect();
+
+    let area = sized_rect(100, 16, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(query_block), area);
+
+    Ok(())
+}
+
+fn build_sparkline<'a>(counts: &[u64]) -> Sparkline<'a> {
+    Sparkline::default()
+        .block(
+            Block::default()
+                .title(format!(
+                    "Messages over time ({} buckets, +/-)",
+                    counts.len()
+                ))
+                .borders(Borders::ALL),
+        )
+        .data(counts)
+        .style(Style::default().fg(FOCUSED_TEXT_COLOR))
+}
+
+pub fn render_statistics_panel(
+    frame: &mut Frame,
+    stats: &Stats,
+    counts: &[u64],
+    (cache_hits, cache_misses): (usize, usize),
+) {
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| String::from("-"));
+    let rows = [
+        ("Total messages", stats.total.to_string()),
+        ("Filtered messages", stats.filtered.to_string()),
+        ("Min data size", or_none(stats.min_size.map(format_size))),
+        ("Max data size", or_none(stats.max_size.map(format_size))),
+        (
+            "Mean data size",
+            or_none(
+                stats
+                    .mean_size
+                    .map(|mean| format_size(mean.round() as usize)),
+            ),
+        ),
+        ("Earliest timestamp", or_none(stats.earliest.clone())),
+        ("Latest timestamp", or_none(stats.latest.clone())),
+        ("Unique sessions", stats.sessions.to_string()),
+        (
+            "Data cache",
+            format!("{cache_hits} hits / {cache_misses} misses"),
+        ),
+    ]
+    .into_iter()
+    .map(|(metric, value)| {
+        Row::new([
+            Cell::from(Span::styled(
+                metric,
+                Style::default().fg(UNFOCUSED_TEXT_COLOR),
+            )),
+            Cell::from(Span::styled(value, Style::default().fg(ROW_FG))),
+        ])
+    });
+
+    let stats_table = Table::new(rows, [Constraint::Length(20), Constraint::Min(10)]).block(
+        Block::default()
+            .title("Statistics of the filtered protos")
+            .borders(Borders::ALL),
+    );
+
+    let area = sized_rect(80, 20, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(Block::default().style(Style::default().bg(BUFFER_BG)), area);
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([Constraint::Length(11), Constraint::Min(3)])
+        .split(area);
+    frame.render_widget(stats_table, chunks[0]);
+    frame.render_widget(build_sparkline(counts), chunks[1]);
+}
+
+pub fn render_size_filter_screen(
+    frame: &mut Frame,
+    input: &InputArena,
+    error: Option<&str>,
+) -> Result<()> {
+    let mut size_block = Block::default()
+        .title("Data size in bytes (min-max)")
+        .borders(Borders::ALL)
+        .style(ACTIVE_STYLE);
+    if let Some(error) = error {
+        size_block = size_block.title_bottom(Span::styled(
+            error.to_owned(),
+            Style::default().fg(Color::Red),
+        ));
+    }
+
+    let area = sized_rect(40, 3, frame.area());
+    frame.render_widget(Clear, area);
+
+    let size_text = Paragraph::new(input.get_content(&InputId::SizeFilter)?.clone())
+        .style(Style::default().fg(FOCUSED_TEXT_COLOR))
+        .block(size_block);
+    frame.render_widget(size_text, area);
+
+    Ok(())
+}
+
+pub fn render_view_parameters_screen(
+    frame: &mut Frame,
+    input: &InputArena,
+    view: &str,
+    fields: &[(String, InputId)],
+    focused: usize,
+) -> Result<()> {
     let popup_block = Block::default()
-        .title("Y/N")
-        .borders(Borders::NONE)
-        .style(Style::default().bg(UNFOCUSED_TEXT_COLOR));
+        .title(format!("Parameters of {view}"))
+        .borders(Borders::ALL)
+        .style(Style::default().bg(BUFFER_BG));
 
-    let exit_text = Text::styled("Would you like to quit ?", Style::default().fg(Color::Red));
+    // one input per parameter and the popup borders
+    let height = u16::try_from(fields.len() * 3 + 2).unwrap_or(u16::MAX);
+    let area = sized_rect(60, height, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup_block, area);
 
-    let exit_paragraph = Paragraph::new(exit_text)
-        .block(popup_block)
-        .wrap(Wrap { trim: false });
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .margin(1)
+        .constraints(vec![Constraint::Length(3); fields.len()])
+        .split(area);
+    for (i, ((name, id), chunk)) in fields.iter().zip(chunks.iter()).enumerate() {
+        let mut block = Block::default().title(name.as_str()).borders(Borders::ALL);
+        if i == focused {
+            block = block.style(ACTIVE_STYLE);
+        }
+        let text = Paragraph::new(input.get_content(id)?.clone()).block(block);
+        frame.render_widget(text, *chunk);
+    }
+
+    Ok(())
+}
+
+pub fn render_view_picker_screen(frame: &mut Frame, views: &[String], selected: usize) {
+    let picker_block = Block::default()
+        .title("Views of the layer")
+        .borders(Borders::ALL)
+        .style(Style::default().bg(BUFFER_BG));
+
+    let lines: Vec<Line> = std::iter::once("All protos")
+        .chain(views.iter().map(String::as_str))
+        .enumerate()
+        .map(|(i, name)| {
+            if i == selected {
+                Line::styled(
+                    format!("▶ {name}"),
+                    Style::default().fg(FOCUSED_TEXT_COLOR).bold(),
+                )
+            } else {
+                Line::styled(format!("  {name}"), Style::default().fg(ROW_FG))
+            }
+        })
+        .collect();
+
+    let area = sized_rect(50, 16, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(picker_block), area);
+}
+
+pub fn render_session_picker_screen(
+    frame: &mut Frame,
+    sessions: &[(usize, usize)],
+    selected: usize,
+) {
+    let picker_block = Block::default()
+        .title("Sessions of the database")
+        .borders(Borders::ALL)
+        .style(Style::default().bg(BUFFER_BG));
+
+    let lines: Vec<Line> = std::iter::once(String::from("All sessions"))
+        .chain(
+            sessions
+                .iter()
+                .map(|(id, count)| format!("Session {id} ({count} messages)")),
+        )
+        .enumerate()
+        .map(|(i, name)| {
+            if i == selected {
+                Line::styled(
+                    format!("▶ {name}"),
+                    Style::default().fg(FOCUSED_TEXT_COLOR).bold(),
+                )
+            } else {
+                Line::styled(format!("  {name}"), Style::default().fg(ROW_FG))
+            }
+        })
+        .collect();
+
+    let area = sized_rect(50, 16, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(picker_block), area);
+}
+
+pub fn render_merge_conflict_screen(frame: &mut Frame, key: &str, ours: &str, theirs: &str) {
+    let conflict_block = Block::default()
+        .title(format!("Conflict on `{key}`"))
+        .borders(Borders::ALL)
+        .style(Style::default().bg(BUFFER_BG));
+
+    let conflict_text = Text::from(vec![
+        Line::styled("ours:", Style::default().fg(UNFOCUSED_TEXT_COLOR)),
+        Line::styled(ours.to_owned(), Style::default().fg(Color::Red)),
+        Line::default(),
+        Line::styled("theirs:", Style::default().fg(UNFOCUSED_TEXT_COLOR)),
+        Line::styled(theirs.to_owned(), Style::default().fg(Color::Green)),
+    ]);
 
-    let area = centered_rect(60, 25, frame.area());
-    frame.render_widget(exit_paragraph, area);
+    let area = sized_rect(100, 16, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(conflict_text)
+            .block(conflict_block)
+            .wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
+pub fn render_exit_screen(frame: &mut Frame, dirty: bool) {
+    if dirty {
+        render_confirmation_dialog(
+            frame,
+            "Unsaved changes. Save and quit (y), quit without saving (d), cancel (n)?",
+            "Save and quit",
+            "Cancel",
+            EXIT_POPUP,
+        );
+    } else {
+        render_confirmation_dialog(
+            frame,
+            "Would you like to quit ?",
+            "Quit",
+            "Stay",
+            EXIT_POPUP,
+        );
+    }
 }
 
 pub fn view(frame: &mut Frame, state: &mut AppState, input: &InputArena) -> Result<()> {
-    render_main_screen(frame, state, input)?;
+    let layout = state.layout;
+    render_main_screen(frame, &layout, state, input)?;
     // for editing ui
     if let CurrentScreen::Editing(focused) = &state.current_screen {
-        render_editing_screen(frame, input, focused)?;
+        render_editing_screen(
+            frame,
+            input,
+            focused,
+            &state.value_type,
+            build_editing_title(state.layer_format, &state.layer_nav_path),
+            state.edit_error.as_deref(),
+            &state.input_overflow_flash,
+        )?;
+    }
+    // annotation popup
+    if let CurrentScreen::Annotating { rowid } = &state.current_screen {
+        render_annotation_screen(frame, input, *rowid)?;
+    }
+    // side-by-side diff of two protos
+    if let CurrentScreen::Diff { left, right } = &state.current_screen {
+        render_diff_screen(frame, (*left, *right), &state.data_diff, state.diff_scroll);
+    }
+    // database schema tree
+    if let CurrentScreen::SchemaView = &state.current_screen {
+        render_schema_screen(frame, &state.schema, state.schema_scroll);
+    }
+    // custom SQL query input
+    if let CurrentScreen::SqlQuery = &state.current_screen {
+        render_sql_query_screen(frame, input, state.sql_error.as_deref())?;
+    }
+    // aggregates of the filtered protos, computed again on every frame to follow the filter
+    if let CurrentScreen::Statistics = &state.current_screen {
+        render_statistics_panel(
+            frame,
+            &state.compute_stats(),
+            &state.bucket_by_time(state.sparkline_buckets),
+            state.cache_stats,
+        );
+    }
+    // data size range popup
+    if let CurrentScreen::SizeFilter = &state.current_screen {
+        render_size_filter_screen(frame, input, state.edit_error.as_deref())?;
+    }
+    // layer views picker
+    if let CurrentScreen::ViewPicker = &state.current_screen {
+        render_view_picker_screen(frame, &state.views, state.view_selected);
+    }
+    // values of the parameters of a view
+    if let CurrentScreen::ViewParameters {
+        view,
+        fields,
+        focused,
+    } = &state.current_screen
+    {
+        render_view_parameters_screen(frame, input, view, fields, *focused)?;
+    }
+    // sessions picker
+    if let CurrentScreen::SessionPicker = &state.current_screen {
+        render_session_picker_screen(frame, &state.picker_sessions(), state.session_selected);
+    }
+    // merge conflict resolution
+    if let CurrentScreen::MergeConflict { key, ours, theirs } = &state.current_screen {
+        render_merge_conflict_screen(frame, key, ours, theirs);
+    }
+    // save confirmation popup
+    if let CurrentScreen::ConfirmSave { diff } = &state.current_screen {
+        render_confirm_save_screen(frame, diff, state.diff_scroll);
+    }
+    // layer key removal popup
+    if let CurrentScreen::ConfirmDelete { path } = &state.current_screen {
+        render_confirm_delete_screen(frame, path);
+    }
+    // new proto popup
+    if let CurrentScreen::AddProto(focused) = &state.current_screen {
+        render_add_proto_screen(frame, input, focused, state.edit_error.as_deref())?;
+    }
+    // proto removal popup
+    if let CurrentScreen::ConfirmDeleteProto { rowid, name } = &state.current_screen {
+        render_confirm_delete_proto_screen(frame, *rowid, name);
     }
     // exit popup
-    if let CurrentScreen::Exiting = &state.current_screen {
-        render_exit_screen(frame);
+    if let CurrentScreen::Exiting { dirty } = &state.current_screen {
+        render_exit_screen(frame, *dirty);
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, buffer::Buffer, Terminal};
+
+    use super::*;
+
+    fn render(widget: impl Widget) -> Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal
+            .draw(|frame| frame.render_widget(widget, frame.area()))
+            .unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    fn is_blank(buffer: &Buffer) -> bool {
+        buffer.content().iter().all(|cell| cell.symbol() == " ")
+    }
+
+    fn headers(count: usize) -> Vec<Header> {
+        (0..count)
+            .map(|i| {
+                Header::from(
+                    i + 1,
+                    Some(i % 3),
+                    format!("proto_{i}"),
+                    String::from("2024-01-01 00:00:00"),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn build_title_renders() {
+        let title = build_title(
+            &Theme::default(),
+            Some("logins"),
+            "capture.db",
+            "layer.toml",
+            true,
+        );
+
+        assert!(!is_blank(&render(title)));
+    }
+
+    #[test]
+    fn build_mode_footer_renders_every_screen() {
+        let screens = vec![
+            CurrentScreen::Main(MainInput::None),
+            CurrentScreen::Main(MainInput::Filter),
+            CurrentScreen::Main(MainInput::Layer),
+            CurrentScreen::Main(MainInput::Command),
+            CurrentScreen::Editing(EditingInput::Key),
+            CurrentScreen::Editing(EditingInput::Value),
+            CurrentScreen::ConfirmSave { diff: Vec::new() },
+            CurrentScreen::Annotating { rowid: 1 },
+            CurrentScreen::Diff { left: 1, right: 2 },
+            CurrentScreen::SchemaView,
+            CurrentScreen::SqlQuery,
+            CurrentScreen::ViewPicker,
+            CurrentScreen::ViewParameters {
+                view: String::from("by_session"),
+                fields: vec![(
+                    String::from(":session"),
+                    InputId::Custom(String::from(":session")),
+                )],
+                focused: 0,
+            },
+            CurrentScreen::SessionPicker,
+            CurrentScreen::SizeFilter,
+            CurrentScreen::Statistics,
+            CurrentScreen::MergeConflict {
+                key: String::from("alias"),
+                ours: String::from("a"),
+                theirs: String::from("b"),
+            },
+            CurrentScreen::ConfirmDelete {
+                path: vec![String::from("alias")],
+            },
+            CurrentScreen::ConfirmDeleteProto {
+                rowid: 1,
+                name: String::from("tcp_login"),
+            },
+            CurrentScreen::AddProto(InputId::NewName),
+            CurrentScreen::Exiting { dirty: true },
+        ];
+        for screen in screens {
+            let state = AppState {
+                current_screen: screen,
+                ..AppState::default()
+            };
+
+            assert!(!is_blank(&render(build_mode_footer(&state, "", "tcp"))));
+        }
+    }
+
+    #[test]
+    fn build_table_renders_without_protos() {
+        let table = build_table(
+            &[],
+            &ColumnId::ALL,
+            &HashSet::new(),
+            &[],
+            &SortConfig::default(),
+            &HashMap::new(),
+            None,
+            None,
+            &Theme::default(),
+        );
+
+        // the header row is still drawn
+        assert!(!is_blank(&render(table)));
+    }
+
+    #[test]
+    fn build_table_renders_many_protos() {
+        let headers = headers(100);
+        let protos: Vec<&Header> = headers.iter().collect();
+        let table = build_table(
+            &protos,
+            &ColumnId::ALL,
+            &HashSet::from([3]),
+            &[5],
+            &SortConfig::default(),
+            &HashMap::new(),
+            None,
+            None,
+            &Theme::default(),
+        );
+
+        assert!(!is_blank(&render(table)));
+    }
+
+    #[test]
+    fn build_highlighted_name_splits_the_matched_runs() {
+        let spans = build_highlighted_name("tcp_login", &[0, 1, 2, 5]);
+
+        let texts: Vec<&str> = spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(texts, vec!["tcp", "_l", "o", "gin"]);
+        assert_eq!(spans[0].style.fg, Some(Color::Yellow));
+        assert_eq!(spans[1].style, Style::default());
+        assert!(build_highlighted_name("", &[]).is_empty());
+    }
+
+    #[test]
+    fn sized_rect_stays_inside_the_area() {
+        let area = Rect::new(0, 0, 80, 24);
+
+        assert_eq!(sized_rect(40, 10, area), Rect::new(20, 7, 40, 10));
+        assert_eq!(sized_rect(160, 40, area), area);
+        assert_eq!(sized_rect(0, 0, area).area(), 0);
+    }
+}